@@ -12,13 +12,54 @@ fn main() {
                 tauri_build::InlinedPlugin::new().commands(&[
                     "connect",
                     "disconnect",
+                    "disconnect_all",
                     "destroy",
                     "send",
+                    "stats",
                 ]),
             )
             .plugin(
                 "download",
-                tauri_build::InlinedPlugin::new().commands(&["file"]),
+                tauri_build::InlinedPlugin::new().commands(&[
+                    "file",
+                    "pause",
+                    "resume",
+                    "cancel",
+                    "list",
+                    "retry",
+                    "restore",
+                    "clear_history",
+                    "reveal",
+                    "reveal_in_folder",
+                    "open_file",
+                    "get_directory",
+                    "set_directory",
+                    "choose_directory",
+                    "get_maximum_size",
+                    "set_maximum_size",
+                    "get_rate_limit",
+                    "set_rate_limit",
+                    "set_download_rate_limit",
+                    "get_metered_threshold",
+                    "set_metered_threshold",
+                    "get_user_agent",
+                    "set_user_agent",
+                    "clear_cookies",
+                    "allow_execution",
+                ]),
+            )
+            .plugin(
+                "upload",
+                tauri_build::InlinedPlugin::new().commands(&["pick", "file", "cancel"]),
+            )
+            .plugin(
+                "media-cache",
+                tauri_build::InlinedPlugin::new().commands(&[
+                    "fetch",
+                    "thumbnail",
+                    "usage",
+                    "clear",
+                ]),
             )
             .plugin(
                 "notifications",
@@ -6,37 +6,53 @@
  * IMPORTS
  * ************************************************************************* */
 
-use futures::stream::{SplitSink, SplitStream, StreamExt};
+use futures::future::join_all;
+use futures::stream::StreamExt;
 use futures::SinkExt;
 use jid::{BareJid, FullJid};
 use log::{debug, error, info, warn};
+use minidom::Element;
 use serde::Serialize;
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tauri::plugin::{Builder, TauriPlugin};
-use tauri::{Emitter, Manager, Runtime, State, Window};
+use tauri::{AppHandle, Emitter, ExitRequestApi, Manager, Runtime, State, Window};
 use thiserror::Error;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use tokio::task::{self, JoinHandle};
-use tokio::time::timeout;
+use tokio::time::{sleep_until, timeout, Instant};
 use tokio_xmpp::connect::ServerConnector;
-use tokio_xmpp::{AsyncClient as Client, Error, Event, Packet};
+use tokio_xmpp::starttls::ServerConfig;
+use tokio_xmpp::{AsyncClient as Client, AsyncConfig as ClientConfig, Error, Event, Packet};
 
 /**************************************************************************
  * CONSTANTS
  * ************************************************************************* */
 
-const EVENT_STATE: &'static str = "connection:state";
-const EVENT_RECEIVE: &'static str = "connection:receive";
+const EVENT_STATE: &str = "connection:state";
+const EVENT_RECEIVE: &str = "connection:receive";
+
+const NS_STREAMS: &str = "http://etherx.jabber.org/streams";
+const NS_STREAM_ERRORS: &str = "urn:ietf:params:xml:ns:xmpp-streams";
 
 const READ_TIMEOUT_MILLISECONDS: u64 = 300000;
+const SHUTDOWN_FLUSH_TIMEOUT_MILLISECONDS: u64 = 2000;
+
+const REDIRECT_PORT_DEFAULT: u16 = 5222;
+const REDIRECT_MAXIMUM: u8 = 3;
+
+const OUTBOX_SIZE_MAXIMUM: usize = 256;
+
+const ACCOUNT_NAME_MAXIMUM_LENGTH: usize = 128;
 
 /**************************************************************************
  * TYPES
  * ************************************************************************* */
 
 type DisconnectError = SendError;
+type ConnectionSpawner = Box<dyn FnOnce() -> ConnectionHandle + Send>;
 
 /**************************************************************************
  * ENUMERATIONS
@@ -52,14 +68,68 @@ pub enum ConnectionState {
     ConnectionTimeout,
 }
 
+#[derive(Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionPhase {
+    #[default]
+    Connecting,
+    Connected,
+    Disconnecting,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ConnectionTransition {
+    Online,
+    Redirected,
+    DisconnectRequested,
+    Closed,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisconnectReason {
+    BadFormat,
+    BadNamespacePrefix,
+    Conflict,
+    ConnectionTimeout,
+    HostGone,
+    HostUnknown,
+    ImproperAddressing,
+    InternalServerError,
+    InvalidFrom,
+    InvalidNamespace,
+    InvalidXml,
+    NotAuthorized,
+    NotWellFormed,
+    PolicyViolation,
+    RemoteConnectionFailed,
+    Reset,
+    ResourceConstraint,
+    RestrictedXml,
+    SeeOtherHost,
+    SystemShutdown,
+    UndefinedCondition,
+    UnsupportedEncoding,
+    UnsupportedFeature,
+    UnsupportedStanzaType,
+    UnsupportedVersion,
+}
+
 #[derive(Serialize, Debug, Error)]
 pub enum ConnectError {
     #[error("Invalid JID, cannot connect")]
     InvalidJid,
+    #[error("Invalid account name, cannot connect")]
+    InvalidAccount,
     #[error("Another connection is bound on the JID")]
     AnotherConnectionBound,
+    #[error("Another connection is bound on the account")]
+    AnotherAccountBound,
     #[error("Connection identifier already exists")]
     ConnectionAlreadyExists,
+    #[error("Connection manager is unavailable")]
+    ManagerUnavailable,
 }
 
 #[derive(Serialize, Debug, Error)]
@@ -70,63 +140,303 @@ pub enum SendError {
     CannotParse,
     #[error("Connection does not exist")]
     ConnectionDoesNotExist,
+    #[error("Too many stanzas waiting for connection")]
+    OutboxFull,
 }
 
-#[derive(Serialize, Debug, Error)]
-pub enum PollInputError {
-    #[error("Authentication error")]
-    AuthenticationError,
-    #[error("Connection error")]
-    ConnectionError,
-    #[error("Timeout error")]
-    TimeoutError,
-    #[error("Other error")]
-    OtherError,
+enum ConnectionCommand {
+    Send(Packet, oneshot::Sender<Result<(), SendError>>),
+    Disconnect,
+    Stats(oneshot::Sender<ConnectionStats>),
+    Shutdown(oneshot::Sender<()>),
 }
 
-#[derive(Serialize, Debug, Error)]
-pub enum PollOutputError {
-    #[error("Packet send error")]
-    PacketSendError,
+enum ManagerCommand {
+    Connect {
+        id: String,
+        account: ConnectionAccount,
+        spawn: ConnectionSpawner,
+        reply: oneshot::Sender<Result<(), ConnectError>>,
+    },
+    Lookup {
+        id: String,
+        reply: oneshot::Sender<Option<UnboundedSender<ConnectionCommand>>>,
+    },
+    Remove {
+        id: String,
+        reply: oneshot::Sender<Option<ConnectionHandle>>,
+    },
+    Drain {
+        reply: oneshot::Sender<Vec<(String, ConnectionHandle)>>,
+    },
 }
 
 /**************************************************************************
  * STRUCTURES
  * ************************************************************************* */
 
-struct ConnectionClient {
+#[derive(Debug, Clone, Copy)]
+struct ConnectionSettings {
+    read_timeout: Duration,
+    takeover: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ConnectionAccount {
+    name: String,
     jid: BareJid,
-    sender: UnboundedSender<Packet>,
-    read_handle: JoinHandle<()>,
-    write_handle: JoinHandle<()>,
+    settings: ConnectionSettings,
+}
+
+#[derive(Debug, Clone)]
+struct ConnectionCredentials {
+    jid: FullJid,
+    password: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ConnectionRedirect {
+    host: String,
+    port: u16,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct StreamError {
+    reason: DisconnectReason,
+    redirect: Option<ConnectionRedirect>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct ConnectionStats {
+    phase: ConnectionPhase,
+    packets_sent: u64,
+    packets_received: u64,
+    redirects: u8,
+}
+
+struct ConnectionHandle {
+    account: ConnectionAccount,
+    commands: UnboundedSender<ConnectionCommand>,
+    task: JoinHandle<()>,
 }
 
 #[derive(Default)]
-pub struct ConnectionClientState {
-    connections: RwLock<HashMap<String, ConnectionClient>>,
+struct ConnectionManager {
+    connections: HashMap<String, ConnectionHandle>,
+}
+
+pub struct ConnectionManagerState {
+    commands: UnboundedSender<ManagerCommand>,
+    shutdown: AtomicBool,
+}
+
+struct ConnectionActor<R: Runtime, C: ConnectionConnector> {
+    window: Window<R>,
+    connector: C,
+    id: String,
+    account: ConnectionAccount,
+    credentials: ConnectionCredentials,
+    phase: ConnectionPhase,
+    stats: ConnectionStats,
+    outbox: VecDeque<Packet>,
+    announced: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct EventConnectionState<'a> {
     id: &'a str,
+    account: &'a str,
     state: ConnectionState,
+    reason: Option<DisconnectReason>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 struct EventConnectionReceive<'a> {
     id: &'a str,
+    account: &'a str,
     stanza: &'a str,
 }
 
+/**************************************************************************
+ * TRAITS
+ * ************************************************************************* */
+
+trait ConnectionConnector: ServerConnector {
+    fn redirect(&self, redirect: &ConnectionRedirect) -> Self;
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl ConnectionConnector for ServerConfig {
+    fn redirect(&self, redirect: &ConnectionRedirect) -> Self {
+        ServerConfig::Manual {
+            host: redirect.host.clone(),
+            port: redirect.port,
+        }
+    }
+}
+
+impl DisconnectReason {
+    fn from_condition(condition: &str) -> Self {
+        match condition {
+            "bad-format" => Self::BadFormat,
+            "bad-namespace-prefix" => Self::BadNamespacePrefix,
+            "conflict" => Self::Conflict,
+            "connection-timeout" => Self::ConnectionTimeout,
+            "host-gone" => Self::HostGone,
+            "host-unknown" => Self::HostUnknown,
+            "improper-addressing" => Self::ImproperAddressing,
+            "internal-server-error" => Self::InternalServerError,
+            "invalid-from" => Self::InvalidFrom,
+            "invalid-namespace" => Self::InvalidNamespace,
+            "invalid-xml" => Self::InvalidXml,
+            "not-authorized" => Self::NotAuthorized,
+            "not-well-formed" => Self::NotWellFormed,
+            "policy-violation" => Self::PolicyViolation,
+            "remote-connection-failed" => Self::RemoteConnectionFailed,
+            "reset" => Self::Reset,
+            "resource-constraint" => Self::ResourceConstraint,
+            "restricted-xml" => Self::RestrictedXml,
+            "see-other-host" => Self::SeeOtherHost,
+            "system-shutdown" => Self::SystemShutdown,
+            "unsupported-encoding" => Self::UnsupportedEncoding,
+            "unsupported-feature" => Self::UnsupportedFeature,
+            "unsupported-stanza-type" => Self::UnsupportedStanzaType,
+            "unsupported-version" => Self::UnsupportedVersion,
+            _ => Self::UndefinedCondition,
+        }
+    }
+}
+
 /**************************************************************************
  * HELPERS
  * ************************************************************************* */
 
-fn emit_connection_abort<R: Runtime>(window: &Window<R>, id: &str, state: ConnectionState) {
-    // Emit connection abort state
-    window
-        .emit(EVENT_STATE, EventConnectionState { id, state })
+fn account_name_from_jid(jid: &BareJid) -> String {
+    // Derive an account name from a bare JID, replacing all characters that \
+    //   are not allowed in event names (eg. '@' and '.')
+    jid.to_string()
+        .chars()
+        .map(|c| if is_account_name_character(c) { c } else { '_' })
+        .collect()
+}
+
+fn is_account_name_character(c: char) -> bool {
+    // Notice: account names are used as a suffix in event names, therefore \
+    //   they must only contain characters that Tauri accepts in event names.
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn is_account_name_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= ACCOUNT_NAME_MAXIMUM_LENGTH
+        && name.chars().all(is_account_name_character)
+}
+
+fn parse_stream_error(stanza: &Element) -> Option<StreamError> {
+    // Not a stream error? (this is a regular stanza)
+    if !stanza.is("error", NS_STREAMS) {
+        return None;
+    }
+
+    // Acquire defined condition (first child in the stream errors namespace)
+    // Notice: a stream error may also hold a 'text' element, which must be \
+    //   ignored there.
+    let condition = stanza
+        .children()
+        .find(|child| child.ns() == NS_STREAM_ERRORS && child.name() != "text");
+
+    let reason = condition
+        .map(|condition| DisconnectReason::from_condition(condition.name()))
+        .unwrap_or(DisconnectReason::UndefinedCondition);
+
+    // Acquire redirect target? (for 'see-other-host' only)
+    let redirect = if reason == DisconnectReason::SeeOtherHost {
+        condition.and_then(|condition| parse_redirect_target(condition.text().trim()))
+    } else {
+        None
+    };
+
+    Some(StreamError { reason, redirect })
+}
+
+fn parse_redirect_target(target: &str) -> Option<ConnectionRedirect> {
+    // Split host from port, which can be either 'host', 'host:port', \
+    //   '[ipv6]' or '[ipv6]:port' (as per RFC 6120, section 4.9.3.19)
+    let (host, port) = if let Some(target) = target.strip_prefix('[') {
+        let (host, rest) = target.split_once(']')?;
+
+        (host, rest.strip_prefix(':'))
+    } else {
+        match target.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (target, None),
+        }
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => REDIRECT_PORT_DEFAULT,
+    };
+
+    Some(ConnectionRedirect {
+        host: host.to_string(),
+        port,
+    })
+}
+
+fn emit_account_event<R: Runtime, S: Serialize + Clone>(
+    emitter: &impl Emitter<R>,
+    event: &str,
+    account: &str,
+    payload: S,
+) {
+    // Emit on the global event channel (for all accounts)
+    emitter.emit(event, payload.clone()).unwrap();
+
+    // Emit on the account-level event channel
+    // Notice: this lets implementors listen for events on a given account \
+    //   only, eg. 'connection:receive:<account>'.
+    emitter
+        .emit(&format!("{}:{}", event, account), payload)
         .unwrap();
+}
+
+fn emit_connection_state<R: Runtime>(
+    emitter: &impl Emitter<R>,
+    id: &str,
+    account: &str,
+    state: ConnectionState,
+    reason: Option<DisconnectReason>,
+) {
+    emit_account_event(
+        emitter,
+        EVENT_STATE,
+        account,
+        EventConnectionState {
+            id,
+            account,
+            state,
+            reason,
+        },
+    );
+}
+
+fn emit_connection_abort<R: Runtime>(
+    emitter: &impl Emitter<R>,
+    id: &str,
+    account: &str,
+    state: ConnectionState,
+    reason: Option<DisconnectReason>,
+) {
+    // Emit connection abort state
+    emit_connection_state(emitter, id, account, state, reason);
 
     // Also emit a disconnected event
     // Notice: this informs the client that the connection is effectively \
@@ -134,181 +444,623 @@ fn emit_connection_abort<R: Runtime>(window: &Window<R>, id: &str, state: Connec
     //   re-emit the disconnected state twice if current state already \
     //   was 'disconnected'.
     if state != ConnectionState::Disconnected {
-        window
-            .emit(
-                EVENT_STATE,
-                EventConnectionState {
-                    id,
-                    state: ConnectionState::Disconnected,
-                },
-            )
-            .unwrap();
+        emit_connection_state(emitter, id, account, ConnectionState::Disconnected, reason);
     }
 }
 
-fn kill_event_handlers(connection: &ConnectionClient) {
-    connection.write_handle.abort();
-    connection.read_handle.abort();
+fn create_client<C: ConnectionConnector>(
+    credentials: &ConnectionCredentials,
+    connector: C,
+) -> Client<C> {
+    let mut client = Client::new_with_config(ClientConfig {
+        jid: credentials.jid.clone().into(),
+        password: credentials.password.clone(),
+        server: connector,
+    });
+
+    // Connections are single-use only
+    client.set_reconnect(false);
+
+    client
 }
 
-fn recover_closed_sender_channel<R: Runtime>(
-    window: &Window<R>,
+fn spawn_connection<R: Runtime, C: ConnectionConnector>(
+    window: Window<R>,
     id: &str,
-    connection: &ConnectionClient,
-) {
-    // Recover from dangling state: emit an implicit disconnected event
-    // Notice: this will prompt the implementor to destroy the client.
-    info!(
-        "Recovering: raising an implicit disconnected event for connection #{}",
-        id
-    );
+    account: &ConnectionAccount,
+    credentials: ConnectionCredentials,
+    connector: C,
+) -> ConnectionHandle {
+    let (commands_tx, commands_rx) = mpsc::unbounded_channel();
 
-    // Abort both task handles (so that no other IPC gets sent)
-    kill_event_handlers(connection);
+    let task = {
+        let actor = ConnectionActor::new(window, id, account.clone(), credentials, connector);
 
-    // Emit connection error event
-    emit_connection_abort(window, id, ConnectionState::ConnectionError);
+        task::spawn(actor.run(commands_rx))
+    };
+
+    ConnectionHandle {
+        account: account.clone(),
+        commands: commands_tx,
+        task,
+    }
 }
 
-async fn poll_input_events<R: Runtime, C: ServerConnector>(
-    window: &Window<R>,
-    id: &str,
-    read_timeout: Duration,
-    mut client_reader: SplitStream<Client<C>>,
-) -> Result<(), PollInputError> {
-    // Wrap client reader in a timeout task; this is especially important \
-    //   since the underlying 'tokio-xmpp' does not implement any kind of \
-    //   timeout whatsoever. This timeout duration is served from the \
-    //   connection initiator, and will most likely depend on the PING \
-    //   interval set by the client.
-    while let Ok(event_maybe) = timeout(read_timeout, client_reader.next()).await {
-        // Handle next event
-        if let Some(result) = handle_next_input_event(window, id, event_maybe) {
-            // We received a non-empty result: we have to stop the loop there!
-            return result;
+async fn wait_connections_closed(connections: Vec<(String, ConnectionHandle)>) {
+    // Request all connection managers to shutdown
+    let replies = connections
+        .iter()
+        .filter_map(|(id, connection)| {
+            let (reply_tx, reply_rx) = oneshot::channel();
+
+            match connection
+                .commands
+                .send(ConnectionCommand::Shutdown(reply_tx))
+            {
+                Ok(_) => Some(reply_rx),
+                Err(_) => {
+                    debug!("Connection #{} was already stopped upon shutdown", id);
+
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Wait for all connection managers to flush their end-of-stream packet \
+    //   (bounded)
+    let flush_timeout = Duration::from_millis(SHUTDOWN_FLUSH_TIMEOUT_MILLISECONDS);
+
+    if timeout(flush_timeout, join_all(replies)).await.is_err() {
+        warn!(
+            "Connections did not all flush in {}ms, aborting remaining",
+            flush_timeout.as_millis()
+        );
+    }
+
+    // Abort all remaining tasks (if any is still running)
+    connections
+        .iter()
+        .for_each(|(_, connection)| connection.task.abort());
+}
+
+/**************************************************************************
+ * MANAGERS
+ * ************************************************************************* */
+
+impl ConnectionPhase {
+    fn next(self, transition: ConnectionTransition) -> Self {
+        match (self, transition) {
+            // Disconnected is a final phase (connections are single-use only)
+            (Self::Disconnected, _) => Self::Disconnected,
+            (_, ConnectionTransition::Closed) => Self::Disconnected,
+
+            // A disconnecting connection can only get closed
+            (Self::Disconnecting, _) => Self::Disconnecting,
+            (_, ConnectionTransition::DisconnectRequested) => Self::Disconnecting,
+
+            // A redirected connection goes back to connecting (on other host)
+            (_, ConnectionTransition::Redirected) => Self::Connecting,
+            (_, ConnectionTransition::Online) => Self::Connected,
         }
     }
+}
 
-    // The next event did not come in due time, consider as timed out
-    warn!(
-        "Timed out waiting {}ms for next event on: #{}",
-        read_timeout.as_millis(),
-        id
-    );
+impl ConnectionManager {
+    async fn run(mut self, mut commands: UnboundedReceiver<ManagerCommand>) {
+        info!("Connection manager has started");
+
+        while let Some(command) = commands.recv().await {
+            self.handle(command).await;
+        }
 
-    // Abort here (timed out)
-    // Notice: the event loop has timed out, abort connection and error out.
-    emit_connection_abort(window, id, ConnectionState::ConnectionTimeout);
+        info!("Connection manager was stopped");
+    }
+
+    async fn handle(&mut self, command: ManagerCommand) {
+        // Notice: replies are ignored if the requester went away meanwhile.
+        match command {
+            ManagerCommand::Connect {
+                id,
+                account,
+                spawn,
+                reply,
+            } => {
+                reply.send(self.connect(id, account, spawn).await).ok();
+            }
+            ManagerCommand::Lookup { id, reply } => {
+                reply
+                    .send(
+                        self.connections
+                            .get(&id)
+                            .map(|connection| connection.commands.clone()),
+                    )
+                    .ok();
+            }
+            ManagerCommand::Remove { id, reply } => {
+                reply.send(self.connections.remove(&id)).ok();
+            }
+            ManagerCommand::Drain { reply } => {
+                reply.send(self.connections.drain().collect()).ok();
+            }
+        }
+    }
 
-    Err(PollInputError::TimeoutError)
+    async fn connect(
+        &mut self,
+        id: String,
+        account: ConnectionAccount,
+        spawn: ConnectionSpawner,
+    ) -> Result<(), ConnectError> {
+        // Assert that connection identifier does not already exist
+        if self.connections.contains_key(&id) {
+            return Err(ConnectError::ConnectionAlreadyExists);
+        }
+
+        // Assert that another connection with this JID or account does not \
+        //   already exist in the global state. This prevents connection \
+        //   manager mis-uses where the implementor client would request \
+        //   multiple parallel connections on the same JID. If a takeover was \
+        //   requested, then the older connection gets gracefully closed \
+        //   instead.
+        let conflicting_ids = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| {
+                connection.account.jid == account.jid || connection.account.name == account.name
+            })
+            .map(|(connection_id, _)| connection_id.to_owned())
+            .collect::<Vec<_>>();
+
+        if !account.settings.takeover {
+            if let Some(connection_id) = conflicting_ids.first() {
+                error!(
+                    "Connection #{} connect request found to conflict with: #{}",
+                    id, connection_id
+                );
+
+                return Err(
+                    if self.connections[connection_id].account.jid == account.jid {
+                        ConnectError::AnotherConnectionBound
+                    } else {
+                        ConnectError::AnotherAccountBound
+                    },
+                );
+            }
+        }
+
+        let taken_over = conflicting_ids
+            .into_iter()
+            .filter_map(|connection_id| {
+                let connection = self.connections.remove(&connection_id)?;
+
+                info!(
+                    "Connection #{} connect request takes over: #{}",
+                    id, connection_id
+                );
+
+                Some((connection_id, connection))
+            })
+            .collect::<Vec<_>>();
+
+        // Request older connections to close (they will emit their own \
+        //   disconnected event), and wait for them to acknowledge (bounded) \
+        //   before spawning the new connection, so that the older streams \
+        //   are ended before the server sees the new one on the same JID.
+        // Notice: this holds other manager commands for at most the flush \
+        //   timeout, which only happens upon takeovers.
+        if !taken_over.is_empty() {
+            wait_connections_closed(taken_over).await;
+        }
+
+        // Spawn connection, and add it in state
+        self.connections.insert(id, spawn());
+
+        info!(
+            "There are now {} connections in the global state: {}",
+            self.connections.len(),
+            self.connections
+                .keys()
+                .map(|id| format!("#{}", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Ok(())
+    }
 }
 
-async fn poll_output_events<C: ServerConnector>(
-    id: &str,
-    mut client_writer: SplitSink<Client<C>, Packet>,
-    mut rx: UnboundedReceiver<Packet>,
-) -> Result<(), PollOutputError> {
-    while let Some(packet) = rx.recv().await {
-        if let Err(err) = client_writer.send(packet).await {
-            error!(
-                "Failed sending packet over connection: #{} because: {}",
-                id, err
-            );
+impl ConnectionManagerState {
+    fn new() -> Self {
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
 
-            return Err(PollOutputError::PacketSendError);
+        task::spawn(ConnectionManager::default().run(commands_rx));
+
+        Self {
+            commands: commands_tx,
+            shutdown: AtomicBool::new(false),
         }
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> ManagerCommand,
+    ) -> Option<T> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.commands.send(command(reply_tx)).ok()?;
 
-        debug!("Sent packet over connection: #{}", id);
+        reply_rx.await.ok()
     }
 
-    Ok(())
+    async fn lookup(&self, id: &str) -> Option<UnboundedSender<ConnectionCommand>> {
+        self.request(|reply| ManagerCommand::Lookup {
+            id: id.to_string(),
+            reply,
+        })
+        .await
+        .flatten()
+    }
 }
 
-fn handle_next_input_event<R: Runtime>(
-    window: &Window<R>,
-    id: &str,
-    event_maybe: Option<Event>,
-) -> Option<Result<(), PollInputError>> {
-    // Any event received? (or no event?)
-    if let Some(event) = event_maybe {
-        match event {
-            Event::Disconnected(Error::Disconnected) => {
-                info!("Received disconnected event on: #{}", id);
+impl<R: Runtime, C: ConnectionConnector> ConnectionActor<R, C> {
+    fn new(
+        window: Window<R>,
+        id: &str,
+        account: ConnectionAccount,
+        credentials: ConnectionCredentials,
+        connector: C,
+    ) -> Self {
+        Self {
+            window,
+            connector,
+            id: id.to_string(),
+            account,
+            credentials,
+            phase: ConnectionPhase::Connecting,
+            stats: ConnectionStats::default(),
+            outbox: VecDeque::new(),
+            announced: false,
+        }
+    }
+
+    async fn run(mut self, mut commands: UnboundedReceiver<ConnectionCommand>) {
+        let read_timeout = self.account.settings.read_timeout;
+
+        info!(
+            "Connection #{} manager has started (with timeout: {}ms)",
+            self.id,
+            read_timeout.as_millis()
+        );
+
+        let mut client = create_client(&self.credentials, self.connector.clone());
+
+        // Wrap client reader in a timeout; this is especially important \
+        //   since the underlying 'tokio-xmpp' does not implement any kind of \
+        //   timeout whatsoever. This timeout duration is served from the \
+        //   connection initiator, and will most likely depend on the PING \
+        //   interval set by the client.
+        let mut read_deadline = Instant::now() + read_timeout;
+
+        while self.phase != ConnectionPhase::Disconnected {
+            tokio::select! {
+                event_maybe = client.next() => {
+                    read_deadline = Instant::now() + read_timeout;
+
+                    self.handle_event(&mut client, event_maybe).await;
+                }
+                command_maybe = commands.recv() => {
+                    if let Some(command) = command_maybe {
+                        self.handle_command(&mut client, command).await;
+                    } else {
+                        // All command senders are gone (connection was \
+                        //   destroyed), stop there
+                        self.transition(ConnectionTransition::Closed);
+                    }
+                }
+                _ = sleep_until(read_deadline) => {
+                    self.handle_timeout();
+                }
+            }
+        }
+
+        info!("Connection #{} manager was stopped", self.id);
+    }
+
+    fn transition(&mut self, transition: ConnectionTransition) {
+        let phase = self.phase.next(transition);
+
+        if phase != self.phase {
+            debug!(
+                "Connection #{} transitioned from phase: {:?} to: {:?}",
+                self.id, self.phase, phase
+            );
+
+            self.phase = phase;
+        }
+    }
+
+    fn abort(&mut self, state: ConnectionState, reason: Option<DisconnectReason>) {
+        emit_connection_abort(&self.window, &self.id, &self.account.name, state, reason);
 
-                emit_connection_abort(window, id, ConnectionState::Disconnected);
+        self.transition(ConnectionTransition::Closed);
+    }
+
+    async fn handle_command(&mut self, client: &mut Client<C>, command: ConnectionCommand) {
+        match command {
+            ConnectionCommand::Send(packet, reply) => {
+                let result = if self.phase == ConnectionPhase::Connected {
+                    self.send(client, packet).await;
+
+                    Ok(())
+                } else if self.phase == ConnectionPhase::Connecting {
+                    // Not connected yet, defer packet until connected \
+                    //   (bounded, as connecting can take a long time)
+                    if self.outbox.len() < OUTBOX_SIZE_MAXIMUM {
+                        self.outbox.push_back(packet);
+
+                        Ok(())
+                    } else {
+                        Err(SendError::OutboxFull)
+                    }
+                } else {
+                    // Notice: packets sent while disconnecting are dropped.
+                    Ok(())
+                };
+
+                reply.send(result).ok();
+            }
+            ConnectionCommand::Disconnect => {
+                self.close(client).await;
+            }
+            ConnectionCommand::Stats(reply) => {
+                reply
+                    .send(ConnectionStats {
+                        phase: self.phase,
+                        ..self.stats
+                    })
+                    .ok();
+            }
+            ConnectionCommand::Shutdown(reply) => {
+                self.close(client).await;
+
+                reply.send(()).ok();
+            }
+        }
+    }
 
-                // Abort here (success)
-                Some(Ok(()))
+    async fn handle_event(&mut self, client: &mut Client<C>, event_maybe: Option<Event>) {
+        let (id, account) = (self.id.as_str(), self.account.name.as_str());
+
+        match event_maybe {
+            Some(Event::Disconnected(Error::Disconnected)) => {
+                info!("Received disconnected event on: #{}", id);
+
+                self.abort(ConnectionState::Disconnected, None);
             }
-            Event::Disconnected(Error::Auth(err)) => {
+            Some(Event::Disconnected(Error::Auth(err))) => {
                 warn!(
                     "Received disconnected event on: #{}, with authentication error: {}",
                     id, err
                 );
 
-                emit_connection_abort(window, id, ConnectionState::AuthenticationFailure);
-
-                // Abort here (error)
-                Some(Err(PollInputError::AuthenticationError))
+                self.abort(ConnectionState::AuthenticationFailure, None);
             }
-            Event::Disconnected(Error::Connection(err)) => {
+            Some(Event::Disconnected(Error::Connection(err))) => {
                 warn!(
                     "Received disconnected event: #{}, with connection error: {}",
                     id, err
                 );
 
-                emit_connection_abort(window, id, ConnectionState::ConnectionError);
-
-                // Abort here (error)
-                Some(Err(PollInputError::ConnectionError))
+                self.abort(ConnectionState::ConnectionError, None);
             }
-            Event::Disconnected(err) => {
+            Some(Event::Disconnected(err)) => {
                 warn!("Received disconnected event: #{}, with error: {}", id, err);
 
-                emit_connection_abort(window, id, ConnectionState::ConnectionError);
-
-                // Abort here (error)
-                Some(Err(PollInputError::OtherError))
+                self.abort(ConnectionState::ConnectionError, None);
             }
-            Event::Online { .. } => {
+            Some(Event::Online { .. }) => {
                 info!("Received connected event on: #{}", id);
 
-                window
-                    .emit(
-                        EVENT_STATE,
-                        EventConnectionState {
-                            id,
-                            state: ConnectionState::Connected,
-                        },
-                    )
-                    .unwrap();
-
-                // Continue
-                None
+                // Announce connection only once, as a redirected connection \
+                //   gets online again (on the other host)
+                if !self.announced {
+                    emit_connection_state(
+                        &self.window,
+                        id,
+                        account,
+                        ConnectionState::Connected,
+                        None,
+                    );
+
+                    self.announced = true;
+                }
+
+                self.transition(ConnectionTransition::Online);
+
+                // Flush all packets that were deferred while connecting
+                while let Some(packet) = self.outbox.pop_front() {
+                    self.send(client, packet).await;
+                }
             }
-            Event::Stanza(stanza) => {
+            Some(Event::Stanza(stanza)) => {
+                self.stats.packets_received += 1;
+
+                // Received a stream error? (the stream is about to be closed)
+                if let Some(stream_error) = parse_stream_error(&stanza) {
+                    warn!(
+                        "Received stream error event on: #{}, with reason: {:?}",
+                        id, stream_error.reason
+                    );
+
+                    match stream_error.redirect {
+                        Some(redirect) => self.redirect(client, redirect),
+                        None => {
+                            self.abort(ConnectionState::ConnectionError, Some(stream_error.reason))
+                        }
+                    }
+
+                    return;
+                }
+
                 debug!("Received stanza event on: #{}", id);
 
                 let stanza_xml = String::from(&stanza);
 
-                window
-                    .emit(
-                        EVENT_RECEIVE,
-                        EventConnectionReceive {
-                            id,
-                            stanza: &stanza_xml,
-                        },
-                    )
-                    .unwrap();
+                emit_account_event(
+                    &self.window,
+                    EVENT_RECEIVE,
+                    account,
+                    EventConnectionReceive {
+                        id,
+                        account,
+                        stanza: &stanza_xml,
+                    },
+                );
+            }
+            None => {
+                // Stop here (no more events)
+                self.transition(ConnectionTransition::Closed);
+            }
+        }
+    }
+
+    fn handle_timeout(&mut self) {
+        // The next event did not come in due time, consider as timed out
+        warn!(
+            "Timed out waiting {}ms for next event on: #{}",
+            self.account.settings.read_timeout.as_millis(),
+            self.id
+        );
 
-                // Continue
-                None
+        // Abort here (timed out)
+        self.abort(ConnectionState::ConnectionTimeout, None);
+    }
+
+    async fn send(&mut self, client: &mut Client<C>, packet: Packet) {
+        if let Err(err) = client.send(packet).await {
+            error!(
+                "Failed sending packet over connection: #{} because: {}",
+                self.id, err
+            );
+
+            // Abort here (cannot write anymore)
+            self.abort(ConnectionState::ConnectionError, None);
+        } else {
+            self.stats.packets_sent += 1;
+
+            debug!("Sent packet over connection: #{}", self.id);
+        }
+    }
+
+    async fn close(&mut self, client: &mut Client<C>) {
+        // Already disconnected or disconnecting? (nothing to do)
+        if self.phase == ConnectionPhase::Disconnected
+            || self.phase == ConnectionPhase::Disconnecting
+        {
+            return;
+        }
+
+        let was_connected = self.phase == ConnectionPhase::Connected;
+
+        self.transition(ConnectionTransition::DisconnectRequested);
+
+        // Consider as disconnected immediately
+        // Notice: this saves some time, instead of waiting for stream end \
+        //   acknowledgement from server which may never come in case of a \
+        //   disconnect request following network issues (thus we would be \
+        //   waiting a long time for the TCP timeout to trigger).
+        emit_connection_abort(
+            &self.window,
+            &self.id,
+            &self.account.name,
+            ConnectionState::Disconnected,
+            None,
+        );
+
+        // Emit end-of-stream packet (requesting a clean disconnection)
+        if was_connected {
+            let flush_timeout = Duration::from_millis(SHUTDOWN_FLUSH_TIMEOUT_MILLISECONDS);
+
+            match timeout(flush_timeout, client.send(Packet::StreamEnd)).await {
+                Ok(Ok(_)) => {
+                    debug!("Sent end-of-stream packet over connection: #{}", self.id)
+                }
+                Ok(Err(err)) => warn!(
+                    "Failed sending end-of-stream packet over connection: #{} because: {}",
+                    self.id, err
+                ),
+                Err(_) => warn!(
+                    "Timed out sending end-of-stream packet over connection: #{}",
+                    self.id
+                ),
             }
         }
-    } else {
-        // Abort here (no more events)
-        Some(Ok(()))
+
+        self.transition(ConnectionTransition::Closed);
     }
+
+    fn redirect(&mut self, client: &mut Client<C>, redirect: ConnectionRedirect) {
+        // Too many redirects? Abort there (this prevents redirect loops)
+        if self.stats.redirects >= REDIRECT_MAXIMUM {
+            warn!(
+                "Connection #{} cannot be redirected to: {}:{}, as it was already redirected {} times",
+                self.id, redirect.host, redirect.port, self.stats.redirects
+            );
+
+            self.abort(
+                ConnectionState::ConnectionError,
+                Some(DisconnectReason::SeeOtherHost),
+            );
+
+            return;
+        }
+
+        info!(
+            "Connection #{} is being redirected to: {}:{}",
+            self.id, redirect.host, redirect.port
+        );
+
+        // Replace client with a client on the other host
+        *client = create_client(&self.credentials, self.connector.redirect(&redirect));
+
+        self.stats.redirects += 1;
+
+        self.transition(ConnectionTransition::Redirected);
+    }
+}
+
+/**************************************************************************
+ * REQUESTS
+ * ************************************************************************* */
+
+async fn request_connect<R: Runtime, C: ConnectionConnector>(
+    window: Window<R>,
+    state: &ConnectionManagerState,
+    id: &str,
+    account: ConnectionAccount,
+    credentials: ConnectionCredentials,
+    connector: C,
+) -> Result<(), ConnectError> {
+    // Request connection manager to register and spawn the new connection
+    // Notice: the connection gets spawned from the connection manager, only \
+    //   once it has asserted that there is no conflict with any other \
+    //   registered connection.
+    let spawn: ConnectionSpawner = {
+        let (id, account) = (id.to_string(), account.clone());
+
+        Box::new(move || spawn_connection(window, &id, &account, credentials, connector))
+    };
+
+    state
+        .request(|reply| ManagerCommand::Connect {
+            id: id.to_string(),
+            account,
+            spawn,
+            reply,
+        })
+        .await
+        .ok_or(ConnectError::ManagerUnavailable)?
 }
 
 /**************************************************************************
@@ -316,13 +1068,16 @@ fn handle_next_input_event<R: Runtime>(
  * ************************************************************************* */
 
 #[tauri::command]
-pub fn connect<R: Runtime>(
+#[allow(clippy::too_many_arguments)]
+pub async fn connect<R: Runtime>(
     window: Window<R>,
-    state: State<'_, ConnectionClientState>,
+    state: State<'_, ConnectionManagerState>,
     id: &str,
     jid: &str,
     password: &str,
     timeout: Option<u64>,
+    account: Option<&str>,
+    takeover: Option<bool>,
 ) -> Result<(), ConnectError> {
     info!("Connection #{} connect requested on JID: {}", id, jid);
 
@@ -330,108 +1085,35 @@ pub fn connect<R: Runtime>(
     let jid_full = FullJid::new(jid).or(Err(ConnectError::InvalidJid))?;
     let jid_bare = jid_full.to_bare();
 
-    // Assert that connection identifier does not already exist
-    if state.connections.read().unwrap().contains_key(id) {
-        return Err(ConnectError::ConnectionAlreadyExists);
-    }
-
-    // Assert that another connection with this JID does not already exist in \
-    //   the global state. This prevents connection manager mis-uses where the \
-    //   implementor client would request multiple parallel connections on the \
-    //   same JID.
-    {
-        // Scan all connections in the state
-        let state_connections = state.connections.read().unwrap();
-
-        for (connection_id, connection) in (&*state_connections).into_iter() {
-            // Found another active connection in the state on the same JID?
-            if jid_bare == connection.jid {
-                error!(
-                    "Connection #{} connect request found to conflict with: #{}",
-                    id, connection_id
-                );
-
-                return Err(ConnectError::AnotherConnectionBound);
-            }
-        }
+    // Acquire account (derived from JID if not provided)
+    let account = ConnectionAccount {
+        name: match account {
+            Some(name) if is_account_name_valid(name) => name.to_string(),
+            Some(_) => return Err(ConnectError::InvalidAccount),
+            None => account_name_from_jid(&jid_bare),
+        },
+        jid: jid_bare,
+        settings: ConnectionSettings {
+            read_timeout: Duration::from_millis(timeout.unwrap_or(READ_TIMEOUT_MILLISECONDS)),
+            takeover: takeover.unwrap_or(false),
+        },
     };
 
-    // Create new client
-    let mut client = Client::new(jid_full, password);
-
-    // Connections are single-use only
-    client.set_reconnect(false);
-
-    // Split client into RX (for writer) and TX (for reader)
-    let (tx, rx) = mpsc::unbounded_channel();
-    let (writer, reader) = client.split();
-
-    // Spawn all tasks
-    let write_handle = {
-        let id = id.to_owned();
-
-        task::spawn(async move {
-            info!("Connection #{} write poller has started", id);
-
-            // Poll for output events
-            if let Err(err) = poll_output_events(&id, writer, rx).await {
-                warn!(
-                    "Connection #{} write poller terminated with error: {}",
-                    id, err
-                );
-            } else {
-                info!("Connection #{} write poller was stopped", id);
-            }
-        })
+    let credentials = ConnectionCredentials {
+        jid: jid_full,
+        password: password.to_string(),
     };
 
-    let read_handle = {
-        let id = id.to_owned();
-        let read_timeout = Duration::from_millis(timeout.unwrap_or(READ_TIMEOUT_MILLISECONDS));
-
-        task::spawn(async move {
-            info!(
-                "Connection #{} read poller has started (with timeout: {}ms)",
-                id,
-                read_timeout.as_millis()
-            );
-
-            // Poll for input events
-            if let Err(err) = poll_input_events(&window, &id, read_timeout, reader).await {
-                warn!(
-                    "Connection #{} read poller terminated with error: {}",
-                    id, err
-                );
-            } else {
-                info!("Connection #{} read poller was stopped", id);
-            }
-        })
-    };
-
-    // Add new connection in state
-    {
-        let mut state_connections = state.connections.write().unwrap();
-
-        state_connections.insert(
-            id.to_string(),
-            ConnectionClient {
-                jid: jid_bare,
-                sender: tx,
-                read_handle,
-                write_handle,
-            },
-        );
-
-        info!(
-            "There are now {} connections in the global state: {}",
-            state_connections.len(),
-            state_connections
-                .keys()
-                .map(|id| format!("#{}", id))
-                .collect::<Vec<_>>()
-                .join(", ")
-        );
-    }
+    // Connect using DNS SRV records resolution for the JID domain
+    request_connect(
+        window,
+        &state,
+        id,
+        account,
+        credentials,
+        ServerConfig::UseSrv,
+    )
+    .await?;
 
     info!("Connection #{} connect request complete", id);
 
@@ -439,56 +1121,56 @@ pub fn connect<R: Runtime>(
 }
 
 #[tauri::command]
-pub fn disconnect<R: Runtime>(
-    window: Window<R>,
+pub async fn disconnect(
     id: &str,
-    state: State<'_, ConnectionClientState>,
+    state: State<'_, ConnectionManagerState>,
 ) -> Result<(), DisconnectError> {
     info!("Connection #{} disconnect requested", id);
 
-    // Send stream end?
-    if let Some(ref connection) = state.connections.read().unwrap().get(id) {
-        // Abort read task handle (so that no other IPC gets sent)
-        connection.read_handle.abort();
-
-        // Emit end-of-stream packet (requesting a clean disconnection)
-        match connection.sender.send(Packet::StreamEnd) {
-            Ok(_) => {
-                info!("Connection #{} disconnect request complete", id);
-
-                // Consider as disconnected immediately
-                // Notice: this saves some time, instead of waiting for stream end \
-                //   acknowledgement from server which may never come in case of a \
-                //   disconnect request following network issues (thus we would be \
-                //   waiting a long time for the TCP timeout to trigger).
-                emit_connection_abort(&window, id, ConnectionState::Disconnected);
-
-                Ok(())
-            }
-            Err(err) => {
-                error!(
-                    "Connection #{} disconnect request failed, because: {}",
-                    id, err
-                );
-
-                // Recover from closed sender channel state (implicitly disconnect)
-                recover_closed_sender_channel(&window, id, connection);
-
-                Err(DisconnectError::CannotWrite)
-            }
-        }
-    } else {
+    let commands = state.lookup(id).await.ok_or_else(|| {
         error!(
             "Connection #{} disconnect request failed, as connection does not exist",
             id
         );
 
-        Err(DisconnectError::ConnectionDoesNotExist)
-    }
+        DisconnectError::ConnectionDoesNotExist
+    })?;
+
+    // Request connection to disconnect
+    // Notice: the connection emits its disconnected event on its own.
+    commands
+        .send(ConnectionCommand::Disconnect)
+        .or(Err(DisconnectError::CannotWrite))?;
+
+    info!("Connection #{} disconnect request complete", id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn disconnect_all(state: State<'_, ConnectionManagerState>) -> Result<(), ()> {
+    info!("Connections disconnect all requested");
+
+    // Remove all connections from the state, and close each of them
+    // Notice: closed connections are removed from the state right away, so \
+    //   that no other packet can be sent on them, and their subsequent \
+    //   destroy requests are no-ops.
+    let connections = state
+        .request(|reply| ManagerCommand::Drain { reply })
+        .await
+        .unwrap_or_default();
+
+    info!("Closing all {} connections", connections.len());
+
+    wait_connections_closed(connections).await;
+
+    info!("Connections disconnect all request complete");
+
+    Ok(())
 }
 
 #[tauri::command]
-pub fn destroy(id: &str, state: State<'_, ConnectionClientState>) -> Result<(), ()> {
+pub async fn destroy(id: &str, state: State<'_, ConnectionManagerState>) -> Result<(), ()> {
     info!("Connection #{} destroy requested", id);
 
     // Remove existing connection?
@@ -497,12 +1179,17 @@ pub fn destroy(id: &str, state: State<'_, ConnectionClientState>) -> Result<(),
     //   has been disconnected, that is, following an explicit or implicit \
     //   disconnection connection state event. The destroy command is solely \
     //   used for garbage collection purposes (ie. stopping background tasks).
-    if let Some(connection) = state.connections.write().unwrap().remove(id) {
-        // Abort both task handles
-        kill_event_handlers(&connection);
+    let connection = state
+        .request(|reply| ManagerCommand::Remove {
+            id: id.to_string(),
+            reply,
+        })
+        .await
+        .flatten();
 
-        // Drop connection sender
-        drop(connection.sender);
+    if let Some(connection) = connection {
+        // Abort connection task handle
+        connection.task.abort();
 
         info!("Connection #{} destroy request complete", id);
     } else {
@@ -516,56 +1203,454 @@ pub fn destroy(id: &str, state: State<'_, ConnectionClientState>) -> Result<(),
 }
 
 #[tauri::command]
-pub fn send<R: Runtime>(
-    window: Window<R>,
+pub async fn send(
     id: &str,
-    state: State<'_, ConnectionClientState>,
+    state: State<'_, ConnectionManagerState>,
     stanza: String,
 ) -> Result<(), SendError> {
     debug!("Connection #{} send requested (will send XMPP stanza)", id);
 
-    if let Some(ref connection) = state.connections.read().unwrap().get(id) {
-        let stanza_root = stanza.parse().or(Err(SendError::CannotParse))?;
+    let commands = state.lookup(id).await.ok_or_else(|| {
+        error!(
+            "Connection #{} send request failed, as connection does not exist",
+            id
+        );
+
+        SendError::ConnectionDoesNotExist
+    })?;
 
-        match connection.sender.send(Packet::Stanza(stanza_root)) {
-            Ok(_) => {
-                debug!(
-                    "Connection #{} send request complete (XMPP stanza was sent)",
-                    id
-                );
+    let stanza_root = stanza.parse().or(Err(SendError::CannotParse))?;
 
-                Ok(())
-            }
-            Err(err) => {
-                error!("Connection #{} send request failed, because: {}", id, err);
+    // Request connection to send stanza
+    // Notice: if this fails, then the connection has stopped, and it has \
+    //   already emitted its disconnected event.
+    let (reply_tx, reply_rx) = oneshot::channel();
 
-                // Recover from closed sender channel state (implicitly disconnect)
-                recover_closed_sender_channel(&window, id, connection);
+    commands
+        .send(ConnectionCommand::Send(
+            Packet::Stanza(stanza_root),
+            reply_tx,
+        ))
+        .ok();
 
-                Err(SendError::CannotWrite)
-            }
+    match reply_rx.await {
+        Ok(Ok(_)) => {
+            debug!(
+                "Connection #{} send request complete (XMPP stanza was sent)",
+                id
+            );
+
+            Ok(())
         }
-    } else {
-        error!(
-            "Connection #{} send request failed, as connection does not exist",
-            id
-        );
+        Ok(Err(err)) => {
+            warn!("Connection #{} send request failed: {}", id, err);
 
-        Err(SendError::ConnectionDoesNotExist)
+            Err(err)
+        }
+        Err(_) => {
+            error!(
+                "Connection #{} send request failed, as connection was stopped",
+                id
+            );
+
+            Err(SendError::CannotWrite)
+        }
     }
 }
 
+#[tauri::command]
+pub async fn stats(
+    id: &str,
+    state: State<'_, ConnectionManagerState>,
+) -> Result<ConnectionStats, SendError> {
+    debug!("Connection #{} stats requested", id);
+
+    let commands = state
+        .lookup(id)
+        .await
+        .ok_or(SendError::ConnectionDoesNotExist)?;
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+
+    commands
+        .send(ConnectionCommand::Stats(reply_tx))
+        .or(Err(SendError::CannotWrite))?;
+
+    reply_rx.await.or(Err(SendError::CannotWrite))
+}
+
+/**************************************************************************
+ * HANDLERS
+ * ************************************************************************* */
+
+pub fn shutdown<R: Runtime>(app: &AppHandle<R>, api: &ExitRequestApi, code: Option<i32>) {
+    // Important: this is called from the main thread upon application exit, \
+    //   which must not be blocked. Therefore, exit is deferred until all \
+    //   connections have flushed their end-of-stream packet on the Tokio \
+    //   reactor (or until the bounded flush delay has passed), and then \
+    //   requested again. Restarts cannot be deferred though, which is why \
+    //   the frontend closes all connections before requesting a restart.
+    let state = app.state::<ConnectionManagerState>();
+
+    // Connections were already closed? (this is the exit requested once done)
+    if state.shutdown.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    api.prevent_exit();
+
+    let app = app.clone();
+
+    task::spawn(async move {
+        let connections = app
+            .state::<ConnectionManagerState>()
+            .request(|reply| ManagerCommand::Drain { reply })
+            .await
+            .unwrap_or_default();
+
+        if !connections.is_empty() {
+            info!(
+                "Closing all {} connections upon shutdown",
+                connections.len()
+            );
+
+            wait_connections_closed(connections).await;
+
+            info!("Connections were all closed upon shutdown");
+        }
+
+        app.exit(code.unwrap_or(0));
+    });
+}
+
 /**************************************************************************
  * PROVIDERS
  * ************************************************************************* */
 
 pub fn provide<R: Runtime>() -> TauriPlugin<R> {
     Builder::new("connection")
-        .invoke_handler(tauri::generate_handler![connect, disconnect, destroy, send])
+        .invoke_handler(tauri::generate_handler![
+            connect,
+            disconnect,
+            disconnect_all,
+            destroy,
+            send,
+            stats
+        ])
         .setup(|app_handle, _| {
-            app_handle.manage(ConnectionClientState::default());
+            app_handle.manage(ConnectionManagerState::new());
 
             Ok(())
         })
         .build()
 }
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod harness;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::time::sleep;
+
+    #[test]
+    fn test_parse_redirect_target() {
+        assert_eq!(
+            parse_redirect_target("xmpp.example.org"),
+            Some(ConnectionRedirect {
+                host: "xmpp.example.org".to_string(),
+                port: 5222
+            })
+        );
+        assert_eq!(
+            parse_redirect_target("xmpp.example.org:5223"),
+            Some(ConnectionRedirect {
+                host: "xmpp.example.org".to_string(),
+                port: 5223
+            })
+        );
+        assert_eq!(
+            parse_redirect_target("[2001:db8::1]"),
+            Some(ConnectionRedirect {
+                host: "2001:db8::1".to_string(),
+                port: 5222
+            })
+        );
+        assert_eq!(
+            parse_redirect_target("[2001:db8::1]:9222"),
+            Some(ConnectionRedirect {
+                host: "2001:db8::1".to_string(),
+                port: 9222
+            })
+        );
+        assert_eq!(parse_redirect_target(""), None);
+        assert_eq!(parse_redirect_target(":5222"), None);
+        assert_eq!(parse_redirect_target("xmpp.example.org:port"), None);
+        assert_eq!(parse_redirect_target("[2001:db8::1"), None);
+    }
+
+    #[test]
+    fn test_parse_stream_error() {
+        let stream_error = |xml: &str| parse_stream_error(&xml.parse::<Element>().unwrap());
+
+        assert_eq!(
+            stream_error(
+                "<error xmlns='http://etherx.jabber.org/streams'>\
+                    <conflict xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+                </error>"
+            ),
+            Some(StreamError {
+                reason: DisconnectReason::Conflict,
+                redirect: None
+            })
+        );
+        assert_eq!(
+            stream_error(
+                "<error xmlns='http://etherx.jabber.org/streams'>\
+                    <text xmlns='urn:ietf:params:xml:ns:xmpp-streams'>Bye</text>\
+                    <system-shutdown xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+                </error>"
+            ),
+            Some(StreamError {
+                reason: DisconnectReason::SystemShutdown,
+                redirect: None
+            })
+        );
+        assert_eq!(
+            stream_error(
+                "<error xmlns='http://etherx.jabber.org/streams'>\
+                    <see-other-host xmlns='urn:ietf:params:xml:ns:xmpp-streams'>\
+                        other.example.org:5223\
+                    </see-other-host>\
+                </error>"
+            ),
+            Some(StreamError {
+                reason: DisconnectReason::SeeOtherHost,
+                redirect: Some(ConnectionRedirect {
+                    host: "other.example.org".to_string(),
+                    port: 5223
+                })
+            })
+        );
+        assert_eq!(
+            stream_error(
+                "<error xmlns='http://etherx.jabber.org/streams'>\
+                    <unknown-condition xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+                </error>"
+            ),
+            Some(StreamError {
+                reason: DisconnectReason::UndefinedCondition,
+                redirect: None
+            })
+        );
+        assert_eq!(
+            stream_error("<message xmlns='jabber:client'><body>Hi</body></message>"),
+            None
+        );
+    }
+
+    fn make_account(name: &str, jid: &str, takeover: bool) -> ConnectionAccount {
+        ConnectionAccount {
+            name: name.to_string(),
+            jid: BareJid::new(jid).unwrap(),
+            settings: ConnectionSettings {
+                read_timeout: Duration::from_millis(READ_TIMEOUT_MILLISECONDS),
+                takeover,
+            },
+        }
+    }
+
+    fn make_spawner(
+        account: &ConnectionAccount,
+    ) -> (ConnectionSpawner, UnboundedReceiver<ConnectionCommand>) {
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        let account = account.clone();
+
+        let spawner: ConnectionSpawner = Box::new(move || ConnectionHandle {
+            account,
+            commands: commands_tx,
+            task: task::spawn(async {}),
+        });
+
+        (spawner, commands_rx)
+    }
+
+    async fn connect_manager(
+        manager: &mut ConnectionManager,
+        id: &str,
+        account: ConnectionAccount,
+    ) -> (
+        Result<(), ConnectError>,
+        UnboundedReceiver<ConnectionCommand>,
+    ) {
+        let (spawner, commands_rx) = make_spawner(&account);
+
+        (
+            manager.connect(id.to_string(), account, spawner).await,
+            commands_rx,
+        )
+    }
+
+    #[test]
+    fn test_connection_phase_transitions() {
+        use ConnectionPhase::*;
+        use ConnectionTransition::*;
+
+        assert_eq!(Connecting.next(Online), Connected);
+        assert_eq!(Connecting.next(Closed), Disconnected);
+        assert_eq!(Connecting.next(DisconnectRequested), Disconnecting);
+        assert_eq!(Connected.next(Online), Connected);
+        assert_eq!(Connected.next(Redirected), Connecting);
+        assert_eq!(Connected.next(DisconnectRequested), Disconnecting);
+        assert_eq!(Connected.next(Closed), Disconnected);
+        assert_eq!(Disconnecting.next(Online), Disconnecting);
+        assert_eq!(Disconnecting.next(Redirected), Disconnecting);
+        assert_eq!(Disconnecting.next(Closed), Disconnected);
+        assert_eq!(Disconnected.next(Online), Disconnected);
+        assert_eq!(Disconnected.next(Redirected), Disconnected);
+        assert_eq!(Disconnected.next(DisconnectRequested), Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_manager_connect_conflicts() {
+        let mut manager = ConnectionManager::default();
+
+        let (result, _) =
+            connect_manager(&mut manager, "1", make_account("a", "a@prose.org", false)).await;
+        assert!(result.is_ok());
+
+        let (result, _) =
+            connect_manager(&mut manager, "1", make_account("b", "b@prose.org", false)).await;
+        assert!(matches!(result, Err(ConnectError::ConnectionAlreadyExists)));
+
+        let (result, _) =
+            connect_manager(&mut manager, "2", make_account("c", "a@prose.org", false)).await;
+        assert!(matches!(result, Err(ConnectError::AnotherConnectionBound)));
+
+        let (result, _) =
+            connect_manager(&mut manager, "2", make_account("a", "c@prose.org", false)).await;
+        assert!(matches!(result, Err(ConnectError::AnotherAccountBound)));
+
+        let (result, _) =
+            connect_manager(&mut manager, "2", make_account("b", "b@prose.org", false)).await;
+        assert!(result.is_ok());
+        assert_eq!(manager.connections.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_manager_connect_takeover() {
+        let mut manager = ConnectionManager::default();
+
+        let (result, mut older_commands_rx) =
+            connect_manager(&mut manager, "1", make_account("a", "a@prose.org", false)).await;
+        assert!(result.is_ok());
+
+        // Act as the older connection, acknowledging its shutdown only after \
+        //   a while (the newer connection must not be spawned before that)
+        let older_closed = Arc::new(AtomicBool::new(false));
+
+        task::spawn({
+            let older_closed = older_closed.clone();
+
+            async move {
+                if let Some(ConnectionCommand::Shutdown(reply)) = older_commands_rx.recv().await {
+                    sleep(Duration::from_millis(100)).await;
+
+                    older_closed.store(true, Ordering::SeqCst);
+                    reply.send(()).ok();
+                }
+            }
+        });
+
+        let account = make_account("a", "a@prose.org", true);
+        let spawned_after_close = Arc::new(AtomicBool::new(false));
+
+        let spawner: ConnectionSpawner = {
+            let (spawner, _) = make_spawner(&account);
+            let (older_closed, spawned_after_close) =
+                (older_closed.clone(), spawned_after_close.clone());
+
+            Box::new(move || {
+                spawned_after_close.store(older_closed.load(Ordering::SeqCst), Ordering::SeqCst);
+
+                spawner()
+            })
+        };
+
+        assert!(manager
+            .connect("2".to_string(), account, spawner)
+            .await
+            .is_ok());
+
+        assert!(spawned_after_close.load(Ordering::SeqCst));
+        assert!(!manager.connections.contains_key("1"));
+        assert!(manager.connections.contains_key("2"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_manager_connect_takeover_timeout() {
+        let mut manager = ConnectionManager::default();
+
+        // The older connection never acknowledges its shutdown
+        let (_, _older_commands_rx) =
+            connect_manager(&mut manager, "1", make_account("a", "a@prose.org", false)).await;
+
+        let started_at = Instant::now();
+
+        let (result, _) =
+            connect_manager(&mut manager, "2", make_account("a", "a@prose.org", true)).await;
+        assert!(result.is_ok());
+
+        assert_eq!(
+            started_at.elapsed(),
+            Duration::from_millis(SHUTDOWN_FLUSH_TIMEOUT_MILLISECONDS)
+        );
+        assert!(manager.connections.contains_key("2"));
+    }
+
+    #[tokio::test]
+    async fn test_manager_remove_and_drain() {
+        let mut manager = ConnectionManager::default();
+
+        let (result, _) =
+            connect_manager(&mut manager, "1", make_account("a", "a@prose.org", false)).await;
+        assert!(result.is_ok());
+
+        let (result, _) =
+            connect_manager(&mut manager, "2", make_account("b", "b@prose.org", false)).await;
+        assert!(result.is_ok());
+
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+
+        manager
+            .handle(ManagerCommand::Remove {
+                id: "1".to_string(),
+                reply: reply_tx,
+            })
+            .await;
+        assert!(matches!(reply_rx.try_recv(), Ok(Some(_))));
+
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+
+        manager
+            .handle(ManagerCommand::Lookup {
+                id: "1".to_string(),
+                reply: reply_tx,
+            })
+            .await;
+        assert!(matches!(reply_rx.try_recv(), Ok(None)));
+
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+
+        manager
+            .handle(ManagerCommand::Drain { reply: reply_tx })
+            .await;
+        assert_eq!(reply_rx.try_recv().map(|all| all.len()).ok(), Some(1));
+        assert!(manager.connections.is_empty());
+    }
+}
@@ -0,0 +1,990 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use super::*;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use jid::Jid;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{PrivateKeyDer, ServerName};
+use rustls::RootCertStore;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+use tauri::{App, Listener, WebviewUrl, WebviewWindowBuilder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::client::TlsStream as ClientTlsStream;
+use tokio_rustls::server::TlsStream as ServerTlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_xmpp::connect::ServerConnectorError;
+use tokio_xmpp::xmpp_stream::XMPPStream;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const TEST_CONNECTION_ID: &str = "test";
+const TEST_ACCOUNT: &str = "test_account";
+const TEST_JID: &str = "user@localhost/test";
+const TEST_USERNAME: &str = "user";
+const TEST_PASSWORD: &str = "password";
+const TEST_HOST: &str = "localhost";
+
+const SCRAM_SALT: &[u8] = b"prose-test-salt";
+const SCRAM_ITERATIONS: u32 = 4096;
+const SCRAM_SERVER_NONCE: &str = "prose-test-server-nonce";
+
+const EVENT_WAIT_TIMEOUT_MILLISECONDS: u64 = 5000;
+const EVENT_SILENCE_MILLISECONDS: u64 = 300;
+const READ_TIMEOUT_TEST_MILLISECONDS: u64 = 400;
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ServerMechanism {
+    Plain,
+    ScramSha256,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ServerScenario {
+    Echo,
+    Silent,
+    AbruptClose,
+    Redirect(u16),
+    Stall,
+}
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug, Clone)]
+struct TestConnector {
+    port: u16,
+    tls: Arc<rustls::ClientConfig>,
+}
+
+#[derive(Debug)]
+struct TestConnectorError(String);
+
+struct TestTls {
+    server: Arc<rustls::ServerConfig>,
+    client: Arc<rustls::ClientConfig>,
+}
+
+#[derive(Debug, Default)]
+struct ServerReport {
+    authenticated: bool,
+    received_stanza: bool,
+    received_stream_end: bool,
+}
+
+struct FakeServer {
+    connector: TestConnector,
+    session: JoinHandle<ServerReport>,
+}
+
+struct Harness {
+    app: App<MockRuntime>,
+    window: Window<MockRuntime>,
+    states: UnboundedReceiver<Value>,
+    account_states: UnboundedReceiver<Value>,
+    receives: UnboundedReceiver<Value>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl fmt::Display for TestConnectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "test connector error: {}", self.0)
+    }
+}
+
+impl std::error::Error for TestConnectorError {}
+
+impl ServerConnectorError for TestConnectorError {}
+
+impl ServerConnector for TestConnector {
+    type Stream = ClientTlsStream<TcpStream>;
+    type Error = TestConnectorError;
+
+    async fn connect(&self, jid: &Jid, ns: &str) -> Result<XMPPStream<Self::Stream>, Self::Error> {
+        // Connect over direct TLS to the fake server (trusting the test CA)
+        let tcp_stream = TcpStream::connect(("127.0.0.1", self.port))
+            .await
+            .map_err(|err| TestConnectorError(err.to_string()))?;
+
+        let tls_stream = TlsConnector::from(self.tls.clone())
+            .connect(ServerName::try_from(TEST_HOST).unwrap(), tcp_stream)
+            .await
+            .map_err(|err| TestConnectorError(err.to_string()))?;
+
+        XMPPStream::start(tls_stream, jid.clone(), ns.to_owned())
+            .await
+            .map_err(|err| TestConnectorError(err.to_string()))
+    }
+}
+
+impl ConnectionConnector for TestConnector {
+    fn redirect(&self, redirect: &ConnectionRedirect) -> Self {
+        // Re-point to the other fake server (all fake servers are local)
+        assert_eq!(redirect.host, TEST_HOST);
+
+        Self {
+            port: redirect.port,
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl TestTls {
+    fn generate() -> Self {
+        // Install TLS provider (if not already installed by another test)
+        rustls::crypto::ring::default_provider()
+            .install_default()
+            .ok();
+
+        // Generate test CA, and server certificate signed by test CA
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "Prose Test CA");
+
+        let ca_certificate = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_certificate = CertificateParams::new(vec![TEST_HOST.to_string()])
+            .unwrap()
+            .signed_by(&server_key, &ca_certificate, &ca_key)
+            .unwrap();
+
+        // Build TLS configurations
+        let server = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![server_certificate.der().clone()],
+                PrivateKeyDer::Pkcs8(server_key.serialize_der().into()),
+            )
+            .unwrap();
+
+        let mut client_roots = RootCertStore::empty();
+
+        client_roots.add(ca_certificate.der().clone()).unwrap();
+
+        let client = rustls::ClientConfig::builder()
+            .with_root_certificates(client_roots)
+            .with_no_client_auth();
+
+        Self {
+            server: Arc::new(server),
+            client: Arc::new(client),
+        }
+    }
+}
+
+impl FakeServer {
+    async fn start(mechanism: ServerMechanism, scenario: ServerScenario) -> Self {
+        Self::start_with_tls(&TestTls::generate(), mechanism, scenario).await
+    }
+
+    async fn start_with_tls(
+        tls: &TestTls,
+        mechanism: ServerMechanism,
+        scenario: ServerScenario,
+    ) -> Self {
+        // Bind server on a random local port
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let acceptor = TlsAcceptor::from(tls.server.clone());
+
+        // Serve a single session
+        let session = task::spawn(async move {
+            let (tcp_stream, _) = listener.accept().await.unwrap();
+            let tls_stream = acceptor.accept(tcp_stream).await.unwrap();
+
+            serve_session(tls_stream, mechanism, scenario).await
+        });
+
+        Self {
+            connector: TestConnector {
+                port,
+                tls: tls.client.clone(),
+            },
+            session,
+        }
+    }
+
+    async fn report(self) -> ServerReport {
+        timeout(
+            Duration::from_millis(EVENT_WAIT_TIMEOUT_MILLISECONDS),
+            self.session,
+        )
+        .await
+        .expect("server session did not end")
+        .unwrap()
+    }
+}
+
+impl Harness {
+    fn new() -> Self {
+        let app = mock_builder()
+            .plugin(provide())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+
+        let window = WebviewWindowBuilder::new(&app, "main", WebviewUrl::default())
+            .build()
+            .unwrap()
+            .as_ref()
+            .window();
+
+        let states = Self::listen(&app, EVENT_STATE.to_string());
+        let account_states = Self::listen(&app, format!("{}:{}", EVENT_STATE, TEST_ACCOUNT));
+        let receives = Self::listen(&app, EVENT_RECEIVE.to_string());
+
+        Self {
+            app,
+            window,
+            states,
+            account_states,
+            receives,
+        }
+    }
+
+    fn listen(app: &App<MockRuntime>, event: String) -> UnboundedReceiver<Value> {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        app.listen_any(event, move |event| {
+            events_tx
+                .send(serde_json::from_str(event.payload()).unwrap())
+                .ok();
+        });
+
+        events_rx
+    }
+
+    async fn connect(&self, server: &FakeServer, password: &str, read_timeout: u64) {
+        let account = ConnectionAccount {
+            name: TEST_ACCOUNT.to_string(),
+            jid: BareJid::new("user@localhost").unwrap(),
+            settings: ConnectionSettings {
+                read_timeout: Duration::from_millis(read_timeout),
+                takeover: false,
+            },
+        };
+
+        let credentials = ConnectionCredentials {
+            jid: FullJid::new(TEST_JID).unwrap(),
+            password: password.to_string(),
+        };
+
+        request_connect(
+            self.window.clone(),
+            &self.app.state::<ConnectionManagerState>(),
+            TEST_CONNECTION_ID,
+            account,
+            credentials,
+            server.connector.clone(),
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn next_event(events: &mut UnboundedReceiver<Value>) -> Value {
+        timeout(
+            Duration::from_millis(EVENT_WAIT_TIMEOUT_MILLISECONDS),
+            events.recv(),
+        )
+        .await
+        .expect("timed out waiting for event")
+        .unwrap()
+    }
+
+    async fn next_states(&mut self, count: usize) -> Vec<String> {
+        let mut states = Vec::new();
+
+        for _ in 0..count {
+            let event = Self::next_event(&mut self.states).await;
+
+            assert_eq!(event["id"], TEST_CONNECTION_ID);
+            assert_eq!(event["account"], TEST_ACCOUNT);
+
+            states.push(event["state"].as_str().unwrap().to_string());
+        }
+
+        states
+    }
+
+    async fn next_account_states(&mut self, count: usize) -> Vec<String> {
+        let mut states = Vec::new();
+
+        for _ in 0..count {
+            let event = Self::next_event(&mut self.account_states).await;
+
+            states.push(event["state"].as_str().unwrap().to_string());
+        }
+
+        states
+    }
+
+    async fn next_stanza(&mut self) -> String {
+        let event = Self::next_event(&mut self.receives).await;
+
+        event["stanza"].as_str().unwrap().to_string()
+    }
+
+    async fn assert_no_more_states(&mut self) {
+        let event = timeout(
+            Duration::from_millis(EVENT_SILENCE_MILLISECONDS),
+            self.states.recv(),
+        )
+        .await;
+
+        assert!(event.is_err(), "unexpected state event: {:?}", event);
+    }
+
+    async fn destroy(&self) {
+        destroy(TEST_CONNECTION_ID, self.app.state()).await.unwrap();
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+async fn read_until(
+    stream: &mut ServerTlsStream<TcpStream>,
+    buffer: &mut String,
+    marker: &str,
+) -> Option<String> {
+    // Read from stream until marker is found in buffer, then drain buffer \
+    //   up to the marker (included)
+    let mut chunk = [0; 4096];
+
+    loop {
+        if let Some(index) = buffer.find(marker) {
+            return Some(buffer.drain(..(index + marker.len())).collect());
+        }
+
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(size) => buffer.push_str(&String::from_utf8_lossy(&chunk[..size])),
+        }
+    }
+}
+
+async fn write_all(stream: &mut ServerTlsStream<TcpStream>, data: &str) {
+    stream.write_all(data.as_bytes()).await.unwrap();
+    stream.flush().await.unwrap();
+}
+
+async fn open_stream(
+    stream: &mut ServerTlsStream<TcpStream>,
+    buffer: &mut String,
+    features: &str,
+) -> Option<()> {
+    // Wait for client stream header, then reply with server stream header
+    read_until(stream, buffer, "<stream:stream").await?;
+    read_until(stream, buffer, ">").await?;
+
+    write_all(
+        stream,
+        &format!(
+            "<?xml version='1.0'?>\
+            <stream:stream xmlns='jabber:client' \
+                xmlns:stream='http://etherx.jabber.org/streams' \
+                id='prose-test' from='{}' version='1.0' xml:lang='en'>\
+            <stream:features>{}</stream:features>",
+            TEST_HOST, features
+        ),
+    )
+    .await;
+
+    Some(())
+}
+
+fn element_text(element: &str) -> &str {
+    // Acquire text contained in a single-level element
+    let start = element.find('>').map(|index| index + 1).unwrap_or(0);
+    let end = element.rfind('<').unwrap_or(element.len());
+
+    element.get(start..end).unwrap_or("")
+}
+
+fn element_attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['\'', '"'] {
+        let prefix = format!("{}={}", name, quote);
+
+        if let Some(start) = element.find(&prefix) {
+            let value = &element[(start + prefix.len())..];
+
+            return value.find(quote).map(|end| &value[..end]);
+        }
+    }
+
+    None
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+async fn authenticate_plain(
+    stream: &mut ServerTlsStream<TcpStream>,
+    buffer: &mut String,
+) -> Option<bool> {
+    let auth = read_until(stream, buffer, "</auth>").await?;
+    let message = BASE64.decode(element_text(&auth)).ok()?;
+
+    Some(message == format!("\0{}\0{}", TEST_USERNAME, TEST_PASSWORD).as_bytes())
+}
+
+async fn authenticate_scram_sha256(
+    stream: &mut ServerTlsStream<TcpStream>,
+    buffer: &mut String,
+) -> Option<bool> {
+    // Receive client first message (eg. 'n,,n=user,r=nonce')
+    let auth = read_until(stream, buffer, "</auth>").await?;
+    let client_first = String::from_utf8(BASE64.decode(element_text(&auth)).ok()?).ok()?;
+
+    let (gs2_header, client_first_bare) = {
+        let index = client_first.match_indices(',').nth(1)?.0;
+
+        (&client_first[..=index], &client_first[(index + 1)..])
+    };
+
+    let client_nonce = client_first_bare
+        .split(',')
+        .find_map(|part| part.strip_prefix("r="))?;
+
+    // Send server first message
+    let nonce = format!("{}{}", client_nonce, SCRAM_SERVER_NONCE);
+
+    let server_first = format!(
+        "r={},s={},i={}",
+        nonce,
+        BASE64.encode(SCRAM_SALT),
+        SCRAM_ITERATIONS
+    );
+
+    write_all(
+        stream,
+        &format!(
+            "<challenge xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>{}</challenge>",
+            BASE64.encode(&server_first)
+        ),
+    )
+    .await;
+
+    // Receive client final message (eg. 'c=biws,r=nonce,p=proof')
+    let response = read_until(stream, buffer, "</response>").await?;
+    let client_final = String::from_utf8(BASE64.decode(element_text(&response)).ok()?).ok()?;
+
+    let (client_final_without_proof, proof) = client_final.rsplit_once(",p=")?;
+
+    if client_final_without_proof != format!("c={},r={}", BASE64.encode(gs2_header), nonce) {
+        return Some(false);
+    }
+
+    // Verify client proof
+    let mut salted_password = [0; 32];
+
+    pbkdf2::pbkdf2_hmac::<Sha256>(
+        TEST_PASSWORD.as_bytes(),
+        SCRAM_SALT,
+        SCRAM_ITERATIONS,
+        &mut salted_password,
+    );
+
+    let auth_message = format!(
+        "{},{},{}",
+        client_first_bare, server_first, client_final_without_proof
+    );
+
+    let client_key = hmac_sha256(&salted_password, b"Client Key");
+    let stored_key = Sha256::digest(&client_key);
+    let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+
+    let client_key_recovered = BASE64
+        .decode(proof)
+        .ok()?
+        .iter()
+        .zip(client_signature.iter())
+        .map(|(proof_byte, signature_byte)| proof_byte ^ signature_byte)
+        .collect::<Vec<_>>();
+
+    if Sha256::digest(&client_key_recovered) != stored_key {
+        return Some(false);
+    }
+
+    // Send server signature (in success)
+    let server_key = hmac_sha256(&salted_password, b"Server Key");
+    let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+
+    write_all(
+        stream,
+        &format!(
+            "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>{}</success>",
+            BASE64.encode(format!("v={}", BASE64.encode(server_signature)))
+        ),
+    )
+    .await;
+
+    Some(true)
+}
+
+async fn serve_session(
+    mut stream: ServerTlsStream<TcpStream>,
+    mechanism: ServerMechanism,
+    scenario: ServerScenario,
+) -> ServerReport {
+    let mut report = ServerReport::default();
+    let mut buffer = String::new();
+
+    // Never answer (the client stays connecting)
+    if scenario == ServerScenario::Stall {
+        std::future::pending::<()>().await;
+    }
+
+    let mechanism_name = match mechanism {
+        ServerMechanism::Plain => "PLAIN",
+        ServerMechanism::ScramSha256 => "SCRAM-SHA-256",
+    };
+
+    // Open pre-authentication stream
+    if open_stream(
+        &mut stream,
+        &mut buffer,
+        &format!(
+            "<mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+                <mechanism>{}</mechanism>\
+            </mechanisms>",
+            mechanism_name
+        ),
+    )
+    .await
+    .is_none()
+    {
+        return report;
+    }
+
+    // Authenticate client
+    let authenticated = match mechanism {
+        ServerMechanism::Plain => authenticate_plain(&mut stream, &mut buffer).await,
+        ServerMechanism::ScramSha256 => authenticate_scram_sha256(&mut stream, &mut buffer).await,
+    };
+
+    match authenticated {
+        Some(true) => {
+            report.authenticated = true;
+
+            // Notice: SCRAM success is sent along with the server signature.
+            if mechanism == ServerMechanism::Plain {
+                write_all(
+                    &mut stream,
+                    "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>",
+                )
+                .await;
+            }
+        }
+        Some(false) => {
+            write_all(
+                &mut stream,
+                "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>",
+            )
+            .await;
+
+            // Wait for client to hang up
+            read_until(&mut stream, &mut buffer, "</stream:stream>").await;
+
+            return report;
+        }
+        None => return report,
+    }
+
+    // Open post-authentication stream, and bind resource
+    if open_stream(
+        &mut stream,
+        &mut buffer,
+        "<bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/>",
+    )
+    .await
+    .is_none()
+    {
+        return report;
+    }
+
+    let Some(bind) = read_until(&mut stream, &mut buffer, "</iq>").await else {
+        return report;
+    };
+
+    write_all(
+        &mut stream,
+        &format!(
+            "<iq type='result' id='{}'>\
+                <bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>{}</jid></bind>\
+            </iq>",
+            element_attribute(&bind, "id").unwrap_or_default(),
+            TEST_JID
+        ),
+    )
+    .await;
+
+    // Run scenario
+    match scenario {
+        ServerScenario::Echo => {
+            if read_until(&mut stream, &mut buffer, "</message>")
+                .await
+                .is_some()
+            {
+                report.received_stanza = true;
+
+                write_all(
+                    &mut stream,
+                    &format!(
+                        "<message from='{}' to='{}' id='pong'><body>pong</body></message>",
+                        TEST_HOST, TEST_JID
+                    ),
+                )
+                .await;
+            }
+
+            if read_until(&mut stream, &mut buffer, "</stream:stream>")
+                .await
+                .is_some()
+            {
+                report.received_stream_end = true;
+
+                write_all(&mut stream, "</stream:stream>").await;
+            }
+
+            stream.shutdown().await.ok();
+        }
+        ServerScenario::Silent => {
+            // Never reply, wait for client to hang up
+            read_until(&mut stream, &mut buffer, "</stream:stream>").await;
+        }
+        ServerScenario::AbruptClose => {
+            // Wait for client to send a stanza (so that it is known to be \
+            //   connected), then reset the socket without closing the stream \
+            //   (nor the TLS session)
+            if read_until(&mut stream, &mut buffer, "</message>")
+                .await
+                .is_some()
+            {
+                report.received_stanza = true;
+            }
+
+            stream.get_ref().0.set_linger(Some(Duration::ZERO)).unwrap();
+
+            drop(stream);
+        }
+        ServerScenario::Redirect(port) => {
+            // Redirect client to the other host, then wait for client to hang up
+            write_all(
+                &mut stream,
+                &format!(
+                    "<stream:error>\
+                        <see-other-host xmlns='{}'>{}:{}</see-other-host>\
+                    </stream:error>\
+                    </stream:stream>",
+                    NS_STREAM_ERRORS, TEST_HOST, port
+                ),
+            )
+            .await;
+
+            read_until(&mut stream, &mut buffer, "</stream:stream>").await;
+        }
+        ServerScenario::Stall => {
+            // Stalled sessions never get there (handled before opening stream)
+        }
+    }
+
+    report
+}
+
+fn assert_aborted_states(states: &[String], state: &str) {
+    // Abort sequences end with a disconnected state (which is not repeated if \
+    //   the abort state already is the disconnected state)
+    if state == "disconnected" {
+        assert_eq!(states, ["disconnected"]);
+    } else {
+        assert_eq!(states, [state, "disconnected"]);
+    }
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_scram_send_receive_disconnect() {
+    let server = FakeServer::start(ServerMechanism::ScramSha256, ServerScenario::Echo).await;
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    assert_eq!(harness.next_states(1).await, ["connected"]);
+
+    send(
+        TEST_CONNECTION_ID,
+        harness.app.state(),
+        "<message xmlns='jabber:client' to='localhost'><body>ping</body></message>".to_string(),
+    )
+    .await
+    .unwrap();
+
+    assert!(harness.next_stanza().await.contains("<body>pong</body>"));
+
+    disconnect(TEST_CONNECTION_ID, harness.app.state())
+        .await
+        .unwrap();
+
+    assert_eq!(harness.next_states(1).await, ["disconnected"]);
+    harness.assert_no_more_states().await;
+
+    assert_eq!(
+        harness.next_account_states(2).await,
+        ["connected", "disconnected"]
+    );
+
+    harness.destroy().await;
+
+    let report = server.report().await;
+
+    assert!(report.authenticated);
+    assert!(report.received_stanza);
+    assert!(report.received_stream_end);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_plain_disconnect() {
+    let server = FakeServer::start(ServerMechanism::Plain, ServerScenario::Echo).await;
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    assert_eq!(harness.next_states(1).await, ["connected"]);
+
+    disconnect(TEST_CONNECTION_ID, harness.app.state())
+        .await
+        .unwrap();
+
+    assert_eq!(harness.next_states(1).await, ["disconnected"]);
+    harness.assert_no_more_states().await;
+
+    // Sending on a disconnected connection must fail
+    assert!(send(
+        TEST_CONNECTION_ID,
+        harness.app.state(),
+        "<presence xmlns='jabber:client'/>".to_string(),
+    )
+    .await
+    .is_err());
+
+    harness.destroy().await;
+
+    assert!(server.report().await.authenticated);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_authentication_failure() {
+    let server = FakeServer::start(ServerMechanism::ScramSha256, ServerScenario::Echo).await;
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, "wrong-password", READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    assert_aborted_states(&harness.next_states(2).await, "authentication-failure");
+    harness.assert_no_more_states().await;
+
+    harness.destroy().await;
+
+    assert!(!server.report().await.authenticated);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_timeout() {
+    let server = FakeServer::start(ServerMechanism::Plain, ServerScenario::Silent).await;
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_TEST_MILLISECONDS)
+        .await;
+
+    assert_eq!(harness.next_states(1).await, ["connected"]);
+    assert_aborted_states(&harness.next_states(2).await, "connection-timeout");
+    harness.assert_no_more_states().await;
+
+    harness.destroy().await;
+    server.session.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_abrupt_socket_close() {
+    let server = FakeServer::start(ServerMechanism::Plain, ServerScenario::AbruptClose).await;
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    assert_eq!(harness.next_states(1).await, ["connected"]);
+
+    send(
+        TEST_CONNECTION_ID,
+        harness.app.state(),
+        "<message xmlns='jabber:client' to='localhost'><body>ping</body></message>".to_string(),
+    )
+    .await
+    .unwrap();
+
+    assert_aborted_states(&harness.next_states(2).await, "connection-error");
+    harness.assert_no_more_states().await;
+
+    harness.destroy().await;
+
+    let report = server.report().await;
+
+    assert!(report.authenticated);
+    assert!(report.received_stanza);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connect_see_other_host_redirect() {
+    let tls = TestTls::generate();
+
+    let target =
+        FakeServer::start_with_tls(&tls, ServerMechanism::Plain, ServerScenario::Echo).await;
+    let server = FakeServer::start_with_tls(
+        &tls,
+        ServerMechanism::Plain,
+        ServerScenario::Redirect(target.connector.port),
+    )
+    .await;
+
+    let mut harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    // Connected to the first host, which is announced only once (although \
+    //   connected again to the other host, once redirected)
+    assert_eq!(harness.next_states(1).await, ["connected"]);
+
+    // Wait for connection to be redirected, so that the stanza goes to the \
+    //   other host (stanzas sent meanwhile are queued until connected there)
+    timeout(
+        Duration::from_millis(EVENT_WAIT_TIMEOUT_MILLISECONDS),
+        async {
+            while stats(TEST_CONNECTION_ID, harness.app.state())
+                .await
+                .unwrap()
+                .redirects
+                == 0
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        },
+    )
+    .await
+    .expect("timed out waiting for redirect");
+
+    send(
+        TEST_CONNECTION_ID,
+        harness.app.state(),
+        "<message xmlns='jabber:client' to='localhost'><body>ping</body></message>".to_string(),
+    )
+    .await
+    .unwrap();
+
+    assert!(harness.next_stanza().await.contains("<body>pong</body>"));
+    harness.assert_no_more_states().await;
+
+    assert_eq!(
+        stats(TEST_CONNECTION_ID, harness.app.state())
+            .await
+            .unwrap()
+            .redirects,
+        1
+    );
+
+    disconnect(TEST_CONNECTION_ID, harness.app.state())
+        .await
+        .unwrap();
+
+    assert_eq!(harness.next_states(1).await, ["disconnected"]);
+    harness.assert_no_more_states().await;
+
+    harness.destroy().await;
+
+    assert!(server.report().await.authenticated);
+
+    let report = target.report().await;
+
+    assert!(report.authenticated);
+    assert!(report.received_stanza);
+    assert!(report.received_stream_end);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_while_connecting_is_bounded() {
+    let server = FakeServer::start(ServerMechanism::Plain, ServerScenario::Stall).await;
+    let harness = Harness::new();
+
+    harness
+        .connect(&server, TEST_PASSWORD, READ_TIMEOUT_MILLISECONDS)
+        .await;
+
+    // Packets are deferred while connecting, up to a limit
+    for _ in 0..OUTBOX_SIZE_MAXIMUM {
+        send(
+            TEST_CONNECTION_ID,
+            harness.app.state(),
+            "<presence xmlns='jabber:client'/>".to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    assert!(matches!(
+        send(
+            TEST_CONNECTION_ID,
+            harness.app.state(),
+            "<presence xmlns='jabber:client'/>".to_string(),
+        )
+        .await,
+        Err(SendError::OutboxFull)
+    ));
+
+    harness.destroy().await;
+    server.session.abort();
+}
@@ -2,35 +2,186 @@
 //
 // Copyright 2024, Prose Foundation
 
+/**************************************************************************
+ * MODULES
+ * ************************************************************************* */
+
+pub mod encryption;
+mod filename;
+mod history;
+mod integrity;
+mod network;
+mod opener;
+mod sanitize;
+mod settings;
+mod sniff;
+pub mod store;
+mod throttle;
+
 /**************************************************************************
  * IMPORTS
  * ************************************************************************* */
 
-use directories::UserDirs;
+use directories::{BaseDirs, UserDirs};
+use jid::BareJid;
 use percent_encoding::percent_decode;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH,
+    CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE, USER_AGENT,
+};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::cmp::min;
-use std::time::Instant;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::io::ErrorKind;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::plugin::{Builder, TauriPlugin};
-use tauri::{Emitter, Runtime, Window};
+use tauri::{Emitter, Manager, Runtime, State, Window};
+use tauri_plugin_dialog::DialogExt;
 use thiserror::Error;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{oneshot, watch, Mutex as AsyncMutex, Semaphore};
+use tokio::time::sleep;
+
+use encryption::{FileDecryptor, FileEncryption, TAG_LENGTH};
+use filename::{content_disposition_filename, mime_extension};
+use history::{DownloadHistory, DownloadRecord, DownloadResumption, DownloadStatus};
+use integrity::{DownloadDigest, DownloadIntegrity};
+use network::{build_client, ResolveError};
+use sanitize::sanitize_filename;
+use settings::DownloadSettings;
+use sniff::{read_head, FileKind};
+use throttle::DownloadThrottle;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const PART_FILE_EXTENSION: &str = "part";
+
+const RETRY_MAXIMUM: u32 = 5;
+const RETRY_DELAY_INITIAL_MILLISECONDS: u64 = 500;
+
+const DOWNLOAD_CONCURRENCY_MAXIMUM: usize = 3;
+
+// Weight given to the latest speed sample, when smoothing download speed
+const SPEED_SMOOTHING_FACTOR: f64 = 0.3;
+
+const DOWNLOAD_DIRECTORY_FALLBACK: &str = "Downloads";
+
+const DOWNLOAD_HEADERS_RESERVED: [&str; 8] = [
+    "host",
+    "range",
+    "if-range",
+    "connection",
+    "content-length",
+    "transfer-encoding",
+    "user-agent",
+    "upgrade",
+];
+
+const FILENAME_ALLOCATE_ATTEMPTS_MAXIMUM: u32 = 1000;
+
+const PROBE_TIMEOUT_MILLISECONDS: u64 = 10000;
+
+#[cfg(target_os = "linux")]
+const XATTR_SENDER_NAME: &str = "user.org.prose.sender";
 
 /**************************************************************************
  * ENUMERATIONS
  * ************************************************************************* */
 
-#[derive(Serialize, Deserialize, Debug, Error, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Error, PartialEq, Eq)]
 pub enum DownloadError {
     #[error("Could not obtain download directory")]
     CouldNotObtainDirectory,
-    #[error("Packet is too small, missing bytes")]
+    #[error("Could not create file")]
     CouldNotCreateFile,
-    #[error("Could not download file")]
-    DownloadError,
-    #[error("Custom error")]
-    CustomError(String),
+    #[error("Could not write file")]
+    CouldNotWriteFile,
+    #[error("Could not move file")]
+    CouldNotMoveFile,
+    #[error("Download already exists")]
+    DownloadAlreadyExists,
+    #[error("Download does not exist")]
+    DownloadDoesNotExist,
+    #[error("Download cancelled")]
+    Cancelled,
+    #[error("Download cannot be retried")]
+    CannotRetry,
+    #[error("Download cannot be revealed")]
+    CannotReveal,
+    #[error("Download cannot be opened")]
+    CannotOpen,
+    #[error("Invalid download directory")]
+    InvalidDirectory,
+    #[error("Invalid download integrity")]
+    InvalidIntegrity,
+    #[error("Download integrity mismatch")]
+    IntegrityMismatch,
+    #[error("Invalid download encryption key")]
+    InvalidEncryptionKey,
+    #[error("Download decryption failed")]
+    DecryptionFailed,
+    #[error("Download is too large")]
+    TooLarge,
+    #[error("Download execution cannot be allowed")]
+    CannotAllowExecution,
+    #[error("Download deferred on metered connection")]
+    Deferred,
+    #[error("Server responded with HTTP status {status}")]
+    HttpStatus { status: u16 },
+    #[error("Could not resolve server address")]
+    DnsFailed,
+    #[error("Could not connect to server")]
+    ConnectionFailed,
+    #[error("Secure connection to server failed")]
+    TlsFailed,
+    #[error("Download timed out")]
+    TimedOut,
+    #[error("Download interrupted")]
+    Interrupted,
+    #[error("Not enough disk space")]
+    DiskFull,
+    #[error("Permission denied")]
+    PermissionDenied,
+    #[error("Invalid download header")]
+    InvalidHeader,
+    #[error("Invalid download account")]
+    InvalidAccount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DownloadControl {
+    Run,
+    Pause,
+    Cancel,
+}
+
+enum DownloadDestination {
+    Directory(PathBuf, String),
+    Path(PathBuf),
+    Reserved(PathBuf),
+}
+
+enum DownloadAttempt {
+    Completed,
+    Paused,
+    Failed(DownloadFailure),
+}
+
+enum DownloadFailure {
+    Transient(DownloadError),
+    Fatal(DownloadError),
 }
 
 /**************************************************************************
@@ -42,6 +193,353 @@ struct EventDownloadProgress {
     id: u64,
     progress: usize,
     total: usize,
+    indeterminate: bool,
+    speed: u64,
+    eta: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadStarted {
+    id: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadCompleted {
+    id: u64,
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadFailed {
+    id: u64,
+    reason: DownloadError,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadCancelled {
+    id: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadRestored {
+    id: u64,
+    resumed: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventDownloadWarning {
+    id: u64,
+    path: String,
+    kind: FileKind,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadOptions {
+    ask_location: Option<bool>,
+    hashes: Option<HashMap<String, String>>,
+    size: Option<u64>,
+    sender: Option<String>,
+    automatic: Option<bool>,
+    headers: Option<HashMap<String, String>>,
+    account: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DownloadValidator {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+struct DownloadProgress {
+    id: u64,
+    downloaded_bytes: usize,
+    total_bytes: Option<usize>,
+    speed: f64,
+    last_size_report: Instant,
+    last_size_report_bytes: usize,
+}
+
+struct DownloadTransfer {
+    validator: Option<DownloadValidator>,
+    progress: DownloadProgress,
+    digest: DownloadDigest,
+    decryptor: Option<FileDecryptor>,
+    maximum_size: Option<u64>,
+    sender: Option<String>,
+    headers: HeaderMap,
+    throttles: Vec<Arc<DownloadThrottle>>,
+}
+
+struct PartFileGuard {
+    path: Option<PathBuf>,
+    placeholder: Option<PathBuf>,
+}
+
+struct DownloadRegistration<'a> {
+    state: &'a DownloadState,
+    id: u64,
+}
+
+pub struct DownloadState {
+    client: Client,
+    queue: Semaphore,
+    downloads: Mutex<HashMap<u64, watch::Sender<DownloadControl>>>,
+    history: Mutex<DownloadHistory>,
+    history_write: AsyncMutex<()>,
+    secret_urls: Mutex<HashMap<u64, String>>,
+    secret_headers: Mutex<HashMap<u64, HeaderMap>>,
+    account_clients: Mutex<HashMap<String, Client>>,
+    default_user_agent: String,
+    settings: Mutex<DownloadSettings>,
+    settings_write: AsyncMutex<()>,
+    throttle: Arc<DownloadThrottle>,
+    throttles: Mutex<HashMap<u64, Arc<DownloadThrottle>>>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl DownloadError {
+    fn from_io_error(error: &std::io::Error, fallback: Self) -> Self {
+        // Classify well-known failures, otherwise report the failed \
+        //   operation (as passed by the caller)
+        match error.kind() {
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded | ErrorKind::FileTooLarge => {
+                Self::DiskFull
+            }
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => Self::PermissionDenied,
+            _ => fallback,
+        }
+    }
+
+    fn from_request_error(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return Self::TimedOut;
+        }
+
+        if error.is_connect() {
+            // DNS resolution and TLS handshake failures both surface as \
+            //   connection errors, and are not exposed as such by reqwest, \
+            //   therefore the underlying causes are inspected
+            let mut source = error.source();
+
+            while let Some(cause) = source {
+                if cause.is::<ResolveError>() {
+                    return Self::DnsFailed;
+                }
+                if cause.is::<rustls::Error>() {
+                    return Self::TlsFailed;
+                }
+
+                // Notice: I/O errors do not expose the error they wrap as \
+                //   their source, therefore it has to be unwrapped there
+                source = match cause.downcast_ref::<std::io::Error>() {
+                    Some(io_error) => {
+                        match io_error.kind() {
+                            ErrorKind::TimedOut => return Self::TimedOut,
+                            ErrorKind::ConnectionRefused
+                            | ErrorKind::ConnectionReset
+                            | ErrorKind::HostUnreachable
+                            | ErrorKind::NetworkUnreachable
+                            | ErrorKind::NetworkDown => return Self::ConnectionFailed,
+                            _ => {}
+                        }
+
+                        io_error
+                            .get_ref()
+                            .map(|inner| inner as &(dyn std::error::Error + 'static))
+                    }
+                    None => cause.source(),
+                };
+            }
+
+            return Self::ConnectionFailed;
+        }
+
+        // Errors while receiving the response body mean that the connection \
+        //   was interrupted
+        if error.is_body() || error.is_decode() {
+            return Self::Interrupted;
+        }
+
+        Self::ConnectionFailed
+    }
+}
+
+impl DownloadTransfer {
+    fn new(id: u64, integrity: &DownloadIntegrity, encryption: Option<&FileEncryption>) -> Self {
+        Self {
+            validator: None,
+            progress: DownloadProgress {
+                id,
+                downloaded_bytes: 0,
+                total_bytes: None,
+                speed: 0.0,
+                last_size_report: Instant::now(),
+                last_size_report_bytes: 0,
+            },
+            digest: integrity.digest(),
+            decryptor: encryption.map(|encryption| encryption.decryptor()),
+            maximum_size: None,
+            sender: None,
+            headers: HeaderMap::new(),
+            throttles: Vec::new(),
+        }
+    }
+
+    fn exceeds_maximum_size(&self, size: u64) -> bool {
+        self.maximum_size
+            .map(|maximum_size| size > maximum_size)
+            .unwrap_or(false)
+    }
+}
+
+impl PartFileGuard {
+    fn disarm(mut self) {
+        self.path = None;
+        self.placeholder = None;
+    }
+}
+
+impl Drop for DownloadRegistration<'_> {
+    fn drop(&mut self) {
+        // Unregister download control and bandwidth limit, whenever the \
+        //   download is over (or it never started)
+        self.state.downloads.lock().unwrap().remove(&self.id);
+        self.state.throttles.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Drop for PartFileGuard {
+    fn drop(&mut self) {
+        // Remove partial file whenever the download did not complete (this \
+        //   also covers downloads that were aborted by being cancelled)
+        if let Some(path) = self.path.take() {
+            std::fs::remove_file(path).ok();
+        }
+
+        // Also release the reserved filename (if any)
+        if let Some(placeholder) = self.placeholder.take() {
+            std::fs::remove_file(placeholder).ok();
+        }
+    }
+}
+
+impl DownloadState {
+    fn new(data_directory: Option<PathBuf>, version: &str) -> Self {
+        let settings = DownloadSettings::load(data_directory.clone());
+
+        // Apply the global bandwidth limit (shared by all downloads)
+        let throttle = DownloadThrottle::new(settings.values.rate_limit);
+
+        Self {
+            client: build_client(false).expect("could not build download client"),
+            queue: Semaphore::new(DOWNLOAD_CONCURRENCY_MAXIMUM),
+            downloads: Mutex::new(HashMap::new()),
+            history: Mutex::new(DownloadHistory::load(data_directory)),
+            history_write: AsyncMutex::new(()),
+            secret_urls: Mutex::new(HashMap::new()),
+            secret_headers: Mutex::new(HashMap::new()),
+            account_clients: Mutex::new(HashMap::new()),
+            default_user_agent: format!("Prose/{} ({})", version, std::env::consts::OS),
+            settings: Mutex::new(settings),
+            settings_write: AsyncMutex::new(()),
+            throttle: Arc::new(throttle),
+            throttles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn client(&self, account: Option<&str>) -> Result<Client, DownloadError> {
+        // Downloads made on behalf of an account share a cookie jar (kept in \
+        //   memory only), other downloads never store cookies
+        let account = match account {
+            Some(account) => account,
+            None => return Ok(self.client.clone()),
+        };
+
+        let mut account_clients = self.account_clients.lock().unwrap();
+
+        if let Some(client) = account_clients.get(account) {
+            return Ok(client.clone());
+        }
+
+        // Notice: building a client only fails if the TLS backend cannot be \
+        //   initialized
+        let client = build_client(true).map_err(|_| DownloadError::TlsFailed)?;
+
+        account_clients.insert(account.to_string(), client.clone());
+
+        Ok(client)
+    }
+
+    fn user_agent(&self) -> String {
+        self.settings
+            .lock()
+            .unwrap()
+            .values
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| self.default_user_agent.clone())
+    }
+
+    fn download_directory(&self) -> Result<PathBuf, DownloadError> {
+        // Acquire the download directory, in order of preference: the \
+        //   user-configured directory (if it still exists), then the system \
+        //   download directory, then a 'Downloads' folder in the home directory
+        let configured_directory = self.settings.lock().unwrap().values.directory.clone();
+
+        if let Some(directory) = configured_directory.filter(|directory| directory.is_dir()) {
+            return Ok(directory);
+        }
+
+        if let Some(directory) = UserDirs::new()
+            .as_ref()
+            .and_then(|user_dirs| user_dirs.download_dir())
+        {
+            return Ok(directory.to_path_buf());
+        }
+
+        let directory = BaseDirs::new()
+            .ok_or(DownloadError::CouldNotObtainDirectory)?
+            .home_dir()
+            .join(DOWNLOAD_DIRECTORY_FALLBACK);
+
+        std::fs::create_dir_all(&directory).map_err(|_| DownloadError::CouldNotObtainDirectory)?;
+
+        Ok(directory)
+    }
+
+    fn set_status(&self, id: u64, status: DownloadStatus) {
+        self.history
+            .lock()
+            .unwrap()
+            .update(id, |record| record.status = status);
+    }
+
+    async fn persist_history(&self) {
+        // Writes are serialized, and the snapshot is taken once the write \
+        //   lock is held, so that an older snapshot never overwrites a newer one
+        let _write_guard = self.history_write.lock().await;
+
+        let snapshot = self.history.lock().unwrap().snapshot();
+
+        if let Some(snapshot) = snapshot {
+            store::write(snapshot).await;
+        }
+    }
+
+    async fn persist_settings(&self) {
+        let _write_guard = self.settings_write.lock().await;
+
+        let snapshot = self.settings.lock().unwrap().snapshot();
+
+        if let Some(snapshot) = snapshot {
+            store::write(snapshot).await;
+        }
+    }
 }
 
 /**************************************************************************
@@ -64,7 +562,7 @@ fn split_filename(filename: &str) -> (String, String) {
     let extension = if filename.contains('.') {
         filename
             .split('.')
-            .last()
+            .next_back()
             .map(|ext| format!(".{}", ext))
             .unwrap_or("".to_string())
     } else {
@@ -76,202 +574,1865 @@ fn split_filename(filename: &str) -> (String, String) {
     (pure_filename.to_string(), extension)
 }
 
-fn remove_path_traversal(filename: &str) -> String {
-    // TODO: path traversal not secure yet
-    // TODO: can't use fs::canonicalize because it doesn't work with \
-    //   non-existing files; many path traversal crates are based on \
-    //   fs::canonicalize, therefore they also can't be used.
-    // 1. Remove control characters
-    // 2. Remove all path separators
-    // 3. Remove path traversal
-    filename
-        .replace(|c| c < ' ', "")
-        .replace(['/', '\\', ':', '~', '@', '?', '[', ']'], "")
-        .replace("..", "")
+fn is_unnamed(filename: &str) -> bool {
+    filename.is_empty() || filename == "undefined"
 }
 
-#[cfg(target_os = "macos")]
-fn mac_set_quarantine(file: &File, application: &str) -> Result<(), std::io::Error> {
-    // This method sets the quarantine flag so that the user cannot just open \
-    //   downloaded file if it is an application
-    // @ref: https://apple.stackexchange.com/questions/256625/\
-    //   how-to-set-restore-the-com-apple-quarantine-attribute
-    use std::ffi::{c_void, CString};
-    use std::os::fd::AsRawFd;
-    use std::time::SystemTime;
-    use uuid::Uuid;
-
-    // Acquire current timestamp
-    let now_timestamp = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    let value = format!(
-        "0081;{:x};{};{}",
-        now_timestamp,
-        application,
-        Uuid::new_v4()
-    );
-
-    let name = CString::new("com.apple.quarantine").unwrap();
-    let value = value.as_bytes();
-    let len = value.len();
-    let value = value.as_ptr() as *const c_void;
+fn resolve_filename(url: &str, filename: &str, headers: Option<&HeaderMap>) -> String {
+    let mut filename = filename.to_string();
 
-    // Set quarantine flag on file
-    let return_value = unsafe {
-        libc::fsetxattr(
-            file.as_raw_fd(),
-            name.as_ptr(),
-            value,
-            len,
-            0,
-            libc::XATTR_CREATE,
-        )
-    };
+    // No filename provided? Then use the name advertised by the server (if \
+    //   any), or the last part of the URL otherwise
+    if is_unnamed(&filename) {
+        let header = |name: HeaderName| headers.and_then(|headers| headers.get(name));
 
-    if return_value != 0 {
-        return Err(std::io::Error::last_os_error());
-    }
+        // Notice: filenames are sometimes sent as raw UTF-8 by servers
+        let disposition_filename = header(CONTENT_DISPOSITION).and_then(|value| {
+            content_disposition_filename(&String::from_utf8_lossy(value.as_bytes()))
+        });
 
-    Ok(())
-}
+        filename = match disposition_filename {
+            Some(disposition_filename) => disposition_filename,
+            None => {
+                let url_path = url.split(['#', '?']).next().unwrap_or(url);
+                let url_fragment = url_path.split('/').next_back().unwrap_or("");
 
-/**************************************************************************
- * COMMANDS
- * ************************************************************************* */
+                let mut url_filename = percent_decode(url_fragment.as_ref())
+                    .decode_utf8_lossy()
+                    .to_string();
 
-#[tauri::command]
-pub async fn file<R: Runtime>(
-    window: Window<R>,
-    id: u64,
-    url: &str,
-    filename: &str,
-) -> Result<String, DownloadError> {
-    let mut filename = filename.to_string();
+                // URLs often do not carry an extension (eg. upload services \
+                //   using random identifiers), therefore guess it from the \
+                //   media type
+                let mime_extension = header(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(mime_extension);
 
-    // No filename provided? Then use the last part of the URL
-    if filename.is_empty() || filename == "undefined" {
-        let url_fragment = url.split('/').last().unwrap_or("");
+                if let Some(mime_extension) = mime_extension {
+                    if !url_filename.is_empty() && split_filename(&url_filename).1.is_empty() {
+                        url_filename = format!("{}.{}", url_filename, mime_extension);
+                    }
+                }
 
-        filename = percent_decode(url_fragment.as_ref())
-            .decode_utf8_lossy()
-            .to_string();
+                url_filename
+            }
+        };
     }
 
     // Security: ensure that provided filename is not attempting to perform a \
     //   path traversal. For instance, passing a filename '../dangerous.txt' \
     //   to store files outside of the Downloads folder. Sanitize the file \
     //   name if it is deemed dangerous.
-    filename = remove_path_traversal(&filename);
+    sanitize_filename(&filename)
+}
 
-    // No filename? Assign fallback filename
-    if filename.is_empty() {
-        filename = "File".to_string();
-    }
+async fn probe_headers(client: &Client, url: &str, headers: &HeaderMap) -> Option<HeaderMap> {
+    // Ask server for file metadata (without downloading it), so that its name \
+    //   can be resolved before the download starts; failures are ignored, as \
+    //   the download itself will report them
+    let response = client
+        .head(url)
+        .headers(headers.clone())
+        .timeout(Duration::from_millis(PROBE_TIMEOUT_MILLISECONDS))
+        .send()
+        .await
+        .ok()?;
 
-    // Acquire the download directory
-    let user_dirs = UserDirs::new().ok_or(DownloadError::CouldNotObtainDirectory)?;
-    let download_dir = user_dirs
-        .download_dir()
-        .ok_or(DownloadError::CouldNotObtainDirectory)?;
+    if response.status().is_success() {
+        Some(response.headers().clone())
+    } else {
+        None
+    }
+}
 
-    // Generate unique filename (if it already exists, otherwise do not change)
-    let mut download_path = download_dir.join(&filename);
-    let (pure_filename, filename_extension) = split_filename(&filename);
+fn build_headers(headers: &HashMap<String, String>) -> Result<HeaderMap, DownloadError> {
+    let mut header_map = HeaderMap::new();
 
-    let mut i = 1;
+    for (name, value) in headers.iter() {
+        let name =
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| DownloadError::InvalidHeader)?;
 
-    while download_path.exists() {
-        download_path = download_dir.join(format!("{pure_filename} ({i}){filename_extension}"));
+        // Refuse headers that are managed by the download itself (eg. to \
+        //   resume downloads), or by the HTTP client
+        if DOWNLOAD_HEADERS_RESERVED.contains(&name.as_str()) {
+            return Err(DownloadError::InvalidHeader);
+        }
 
-        i += 1;
+        header_map.insert(
+            name,
+            HeaderValue::from_str(value).map_err(|_| DownloadError::InvalidHeader)?,
+        );
     }
 
-    // Download file
-    let mut response = reqwest::get(url)
-        .await
-        .map_err(|_| DownloadError::DownloadError)?;
+    Ok(header_map)
+}
 
-    // Create file on filesystem
-    let mut file = File::create(&download_path)
-        .await
-        .map_err(|_| DownloadError::CouldNotCreateFile)?;
+async fn ask_save_path<R: Runtime>(
+    window: &Window<R>,
+    directory: &Path,
+    filename: &str,
+) -> Option<PathBuf> {
+    // Ask user where to save file, starting from the download directory
+    let (path_tx, path_rx) = oneshot::channel();
 
-    #[cfg(target_os = "macos")]
-    {
-        mac_set_quarantine(&file, "Prose")
-            .map_err(|e| DownloadError::CustomError(e.to_string()))?;
-    }
+    window
+        .dialog()
+        .file()
+        .set_parent(window)
+        .set_directory(directory)
+        .set_file_name(filename)
+        .save_file(move |path| {
+            path_tx.send(path).ok();
+        });
 
-    // Compute total download size
-    let total_bytes = response.content_length().unwrap_or(0) as usize;
-    let mut downloaded_bytes = 0;
-    let mut last_size_report = Instant::now();
+    path_rx.await.ok()??.into_path().ok()
+}
 
-    // Drain bytes from HTTP response to file
-    while let Some(chunk) = response
-        .chunk()
-        .await
-        .map_err(|_| DownloadError::DownloadError)?
-    {
-        // Write received bytes
-        file.write_all(&chunk)
-            .await
-            .map_err(|_| DownloadError::DownloadError)?;
+async fn ask_directory<R: Runtime>(window: &Window<R>, directory: &Path) -> Option<PathBuf> {
+    let (path_tx, path_rx) = oneshot::channel();
 
-        // Compute download progress (de-bounced)
-        downloaded_bytes = min(downloaded_bytes + chunk.len(), total_bytes);
+    window
+        .dialog()
+        .file()
+        .set_parent(window)
+        .set_directory(directory)
+        .pick_folder(move |path| {
+            path_tx.send(path).ok();
+        });
 
-        if last_size_report.elapsed().as_millis() > 100 || downloaded_bytes == total_bytes {
-            last_size_report = Instant::now();
+    path_rx.await.ok()??.into_path().ok()
+}
 
-            window
-                .emit(
-                    "download:progress",
-                    EventDownloadProgress {
-                        id,
-                        progress: downloaded_bytes,
-                        total: total_bytes,
-                    },
-                )
-                .unwrap();
+fn part_path(download_path: &Path) -> PathBuf {
+    // Partial data is stored next to the final file, with an appended \
+    //   extension (eg. 'file.txt' becomes 'file.txt.part')
+    let mut part_path = download_path.as_os_str().to_owned();
+
+    part_path.push(".");
+    part_path.push(PART_FILE_EXTENSION);
+
+    PathBuf::from(part_path)
+}
+
+fn create_new_file(path: &Path) -> Result<(), std::io::Error> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map(|_| ())
+}
+
+fn allocate_path(directory: &Path, filename: &str) -> Result<PathBuf, DownloadError> {
+    // Reserve a unique filename atomically, by creating an empty placeholder \
+    //   file that fails if the file already exists (instead of checking for \
+    //   existence first, which would race with concurrent downloads of the \
+    //   same filename), then its partial file the same way; the placeholder \
+    //   gets replaced by the partial file once the download completes.
+    // Notice: this is synchronous on purpose, so that a cancelled download \
+    //   cannot leave a placeholder behind without its partial file.
+    let (pure_filename, filename_extension) = split_filename(filename);
+
+    for i in 0..FILENAME_ALLOCATE_ATTEMPTS_MAXIMUM {
+        let download_path = if i == 0 {
+            directory.join(filename)
+        } else {
+            directory.join(format!("{pure_filename} ({i}){filename_extension}"))
+        };
+
+        match create_new_file(&download_path) {
+            Ok(_) => {}
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => {
+                return Err(DownloadError::from_io_error(
+                    &error,
+                    DownloadError::CouldNotCreateFile,
+                ))
+            }
         }
-    }
 
-    // Flush downloaded file on disk
-    file.flush()
-        .await
-        .map_err(|_| DownloadError::DownloadError)?;
+        match create_new_file(&part_path(&download_path)) {
+            Ok(_) => return Ok(download_path),
+            Err(error) => {
+                std::fs::remove_file(&download_path).ok();
 
-    // Bounce Dock icon for Downloads folder
-    #[cfg(target_os = "macos")]
-    {
-        notifications::misc::make_download_bounce(download_path.to_string_lossy().as_ref());
+                if error.kind() != ErrorKind::AlreadyExists {
+                    return Err(DownloadError::from_io_error(
+                        &error,
+                        DownloadError::CouldNotCreateFile,
+                    ));
+                }
+            }
+        }
     }
 
-    Ok(download_path.to_string_lossy().to_string())
+    Err(DownloadError::CouldNotCreateFile)
 }
 
-/**************************************************************************
- * PROVIDERS
- * ************************************************************************* */
+fn parse_content_range_total(content_range: &str) -> Option<usize> {
+    // Parse complete length from a 'Content-Range' header value \
+    //   (eg. 'bytes 200-999/1000' gives 1000, while 'bytes 200-999/*' \
+    //   gives nothing as the complete length is unknown)
+    content_range
+        .trim()
+        .strip_prefix("bytes ")?
+        .split('/')
+        .nth(1)?
+        .trim()
+        .parse()
+        .ok()
+}
 
-pub fn provide<R: Runtime>() -> TauriPlugin<R> {
-    Builder::new("download")
-        .invoke_handler(tauri::generate_handler![file])
-        .setup(|_, _| Ok(()))
-        .build()
+fn is_transient_status(status: StatusCode) -> bool {
+    // Those statuses are expected to go away if the request is retried later
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
 }
 
-/**************************************************************************
- * TESTS
- * ************************************************************************* */
+fn retry_delay(retries: u32) -> Duration {
+    // Exponential back-off, starting from initial delay
+    Duration::from_millis(RETRY_DELAY_INITIAL_MILLISECONDS * 2_u64.pow(retries))
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl DownloadValidator {
+    fn from_response(response: &Response) -> Self {
+        Self::from_headers(response.headers())
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    fn from_resumption(resumption: &DownloadResumption) -> Option<Self> {
+        let validator = Self {
+            etag: resumption.etag.clone(),
+            last_modified: resumption.last_modified.clone(),
+        };
+
+        if validator.if_range().is_some() {
+            Some(validator)
+        } else {
+            None
+        }
+    }
+
+    fn if_range(&self) -> Option<&str> {
+        // Prefer strong entity tags over dates, as advised by RFC 9110; weak \
+        //   entity tags cannot be used in 'If-Range'
+        match (&self.etag, &self.last_modified) {
+            (Some(etag), _) if !etag.starts_with("W/") => Some(etag),
+            (_, Some(last_modified)) => Some(last_modified),
+            _ => None,
+        }
+    }
+}
+
+impl DownloadProgress {
+    fn advance(&mut self, amount: usize) {
+        // Never report more than the total size (if known, as servers may \
+        //   send more bytes than announced)
+        self.downloaded_bytes += amount;
+
+        if let Some(total_bytes) = self.total_bytes {
+            self.downloaded_bytes = min(self.downloaded_bytes, total_bytes);
+        }
+    }
+
+    fn sample_speed(&mut self, now: Instant) {
+        // Compute download speed since last report, smoothed over previous \
+        //   reports (so that the estimated time remaining does not jump around)
+        let elapsed = now.saturating_duration_since(self.last_size_report);
+
+        if !elapsed.is_zero() {
+            let sample = self
+                .downloaded_bytes
+                .saturating_sub(self.last_size_report_bytes) as f64
+                / elapsed.as_secs_f64();
+
+            self.speed = if self.speed > 0.0 {
+                SPEED_SMOOTHING_FACTOR * sample + (1.0 - SPEED_SMOOTHING_FACTOR) * self.speed
+            } else {
+                sample
+            };
+        }
+    }
+
+    fn eta(&self) -> Option<u64> {
+        // Estimate time remaining (in seconds), if total size is known
+        let remaining_bytes = self.total_bytes?.saturating_sub(self.downloaded_bytes);
+
+        if remaining_bytes == 0 {
+            Some(0)
+        } else if self.speed > 0.0 {
+            Some((remaining_bytes as f64 / self.speed).ceil() as u64)
+        } else {
+            None
+        }
+    }
+
+    fn report<R: Runtime>(&mut self, window: &Window<R>, force: bool) {
+        // Report download progress (de-bounced); forced reports happen when \
+        //   the transfer (re-)starts, therefore the speed is not sampled, \
+        //   as time elapsed since last report was not spent downloading
+        let now = Instant::now();
+
+        if force
+            || now
+                .saturating_duration_since(self.last_size_report)
+                .as_millis()
+                > 100
+            || Some(self.downloaded_bytes) == self.total_bytes
+        {
+            if !force {
+                self.sample_speed(now);
+            }
+
+            self.last_size_report = now;
+            self.last_size_report_bytes = self.downloaded_bytes;
+
+            window
+                .emit(
+                    "download:progress",
+                    EventDownloadProgress {
+                        id: self.id,
+                        progress: self.downloaded_bytes,
+                        total: self.total_bytes.unwrap_or(0),
+                        indeterminate: self.total_bytes.is_none(),
+                        speed: self.speed.round() as u64,
+                        eta: self.eta(),
+                    },
+                )
+                .unwrap();
+        }
+    }
+}
+
+async fn download_attempt<R: Runtime>(
+    window: &Window<R>,
+    client: &Client,
+    url: &str,
+    file: &mut File,
+    transfer: &mut DownloadTransfer,
+    control: &mut watch::Receiver<DownloadControl>,
+) -> DownloadAttempt {
+    // Resume from the end of the partial file (if any data was already \
+    //   downloaded, and if the resource can be validated not to have \
+    //   changed). Notice: the digest must have been computed over the exact \
+    //   data already in the partial file for it to be resumed, and \
+    //   decryption cannot be resumed since the authentication state of \
+    //   previously received data is not kept.
+    let offset = file
+        .metadata()
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    let mut request = client.get(url).headers(transfer.headers.clone());
+
+    let if_range = transfer
+        .validator
+        .as_ref()
+        .and_then(|validator| validator.if_range());
+
+    let resumable = offset > 0
+        && if_range.is_some()
+        && transfer.digest.length() == offset
+        && transfer.decryptor.is_none();
+
+    if resumable {
+        if let Some(if_range) = if_range {
+            request = request
+                .header(RANGE, format!("bytes={}-", offset))
+                .header(IF_RANGE, if_range);
+        }
+    }
+
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(error) => {
+            return DownloadAttempt::Failed(DownloadFailure::Transient(
+                DownloadError::from_request_error(&error),
+            ))
+        }
+    };
+
+    let status = response.status();
+    let status_error = DownloadError::HttpStatus {
+        status: status.as_u16(),
+    };
+
+    // Range not satisfiable? Forget about the validator, so that next \
+    //   attempt restarts from scratch
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        transfer.validator = None;
+
+        return DownloadAttempt::Failed(DownloadFailure::Transient(status_error));
+    }
+
+    if is_transient_status(status) {
+        return DownloadAttempt::Failed(DownloadFailure::Transient(status_error));
+    }
+
+    if !status.is_success() {
+        return DownloadAttempt::Failed(DownloadFailure::Fatal(status_error));
+    }
+
+    let response_validator = DownloadValidator::from_response(&response);
+
+    // Partial content? Append to the partial file, otherwise the server \
+    //   either does not support ranges or the resource has changed, so \
+    //   restart from scratch.
+    if status == StatusCode::PARTIAL_CONTENT {
+        // Partial content does not match partial file? Forget about the \
+        //   validator, so that next attempt restarts from scratch
+        if !resumable || transfer.validator.as_ref() != Some(&response_validator) {
+            transfer.validator = None;
+
+            return DownloadAttempt::Failed(DownloadFailure::Transient(DownloadError::Interrupted));
+        }
+
+        transfer.progress.downloaded_bytes = offset as usize;
+
+        transfer.progress.total_bytes = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range_total)
+            .or_else(|| {
+                response
+                    .content_length()
+                    .map(|length| offset as usize + length as usize)
+            });
+    } else {
+        if let Err(error) = file.set_len(0).await {
+            return DownloadAttempt::Failed(DownloadFailure::Fatal(DownloadError::from_io_error(
+                &error,
+                DownloadError::CouldNotWriteFile,
+            )));
+        }
+
+        transfer.digest.reset();
+
+        if let Some(ref mut decryptor) = transfer.decryptor {
+            decryptor.reset();
+        }
+
+        transfer.progress.downloaded_bytes = 0;
+        transfer.progress.total_bytes = response.content_length().map(|length| length as usize);
+    }
+
+    remember_resumption(window, transfer.progress.id, |resumption| {
+        resumption.etag = response_validator.etag.clone();
+        resumption.last_modified = response_validator.last_modified.clone();
+    })
+    .await;
+
+    transfer.validator = Some(response_validator);
+
+    // Refuse files larger than allowed, as announced by the server (the \
+    //   authentication tag of encrypted files is not part of the file)
+    let announced_size =
+        transfer
+            .progress
+            .total_bytes
+            .map(|total_bytes| match transfer.decryptor {
+                Some(_) => total_bytes.saturating_sub(TAG_LENGTH),
+                None => total_bytes,
+            });
+
+    if announced_size.is_some_and(|size| transfer.exceeds_maximum_size(size as u64)) {
+        return DownloadAttempt::Failed(DownloadFailure::Fatal(DownloadError::TooLarge));
+    }
+
+    transfer.progress.report(window, true);
+
+    // Drain bytes from HTTP response to file (until paused)
+    loop {
+        let chunk = tokio::select! {
+            chunk = response.chunk() => chunk,
+            _ = control.wait_for(|control| *control == DownloadControl::Pause) => {
+                return DownloadAttempt::Paused;
+            }
+        };
+
+        let chunk = match chunk {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(error) => {
+                return DownloadAttempt::Failed(DownloadFailure::Transient(
+                    DownloadError::from_request_error(&error),
+                ))
+            }
+        };
+
+        // Decrypt received bytes (if download is encrypted)
+        let data = match transfer.decryptor {
+            Some(ref mut decryptor) => Cow::Owned(decryptor.update(&chunk)),
+            None => Cow::Borrowed(&chunk[..]),
+        };
+
+        // Write received bytes
+        if let Err(error) = file.write_all(&data).await {
+            return DownloadAttempt::Failed(DownloadFailure::Fatal(DownloadError::from_io_error(
+                &error,
+                DownloadError::CouldNotWriteFile,
+            )));
+        }
+
+        // Compute integrity digest (stopping early if file is larger than \
+        //   expected)
+        transfer.digest.update(&data);
+
+        if transfer.digest.exceeds_size() {
+            return DownloadAttempt::Failed(DownloadFailure::Fatal(
+                DownloadError::IntegrityMismatch,
+            ));
+        }
+
+        // Also stop as soon as the file gets larger than allowed (servers \
+        //   might not announce sizes, or announce wrong sizes)
+        if transfer.exceeds_maximum_size(transfer.digest.length()) {
+            return DownloadAttempt::Failed(DownloadFailure::Fatal(DownloadError::TooLarge));
+        }
+
+        // Compute download progress
+        transfer.progress.advance(chunk.len());
+
+        transfer.progress.report(window, false);
+
+        // Throttle download (waiting for the most restrictive of the global \
+        //   and per-download bandwidth limits, if any)
+        let delay = transfer
+            .throttles
+            .iter()
+            .map(|throttle| throttle.consume(chunk.len() as u64))
+            .max()
+            .unwrap_or(Duration::ZERO);
+
+        if !delay.is_zero() {
+            tokio::select! {
+                _ = sleep(delay) => {},
+                _ = control.wait_for(|control| *control == DownloadControl::Pause) => {
+                    return DownloadAttempt::Paused;
+                }
+            }
+        }
+    }
+
+    DownloadAttempt::Completed
+}
+
+async fn download_to_part<R: Runtime>(
+    window: &Window<R>,
+    client: &Client,
+    url: &str,
+    part_path: &Path,
+    mut transfer: DownloadTransfer,
+    mut control: watch::Receiver<DownloadControl>,
+) -> Result<(), DownloadError> {
+    // Create partial file on filesystem
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .await
+        .map_err(|error| DownloadError::from_io_error(&error, DownloadError::CouldNotCreateFile))?;
+
+    // Quarantine partial file only when first created (a resumed or \
+    //   restored partial file was already quarantined)
+    #[cfg(target_os = "macos")]
+    {
+        let created = file
+            .metadata()
+            .await
+            .map_err(|error| {
+                DownloadError::from_io_error(&error, DownloadError::CouldNotCreateFile)
+            })?
+            .len()
+            == 0;
+
+        if created {
+            mac_set_quarantine(&file, "Prose").map_err(|error| {
+                DownloadError::from_io_error(&error, DownloadError::CouldNotCreateFile)
+            })?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        linux_withhold_execution(&file).await.map_err(|error| {
+            DownloadError::from_io_error(&error, DownloadError::CouldNotCreateFile)
+        })?;
+
+        linux_set_origin(&file, url, transfer.sender.as_deref());
+    }
+
+    let mut retries = 0;
+
+    // Download file (resuming after pauses and transient failures)
+    loop {
+        let downloaded_bytes_before = transfer.progress.downloaded_bytes;
+
+        let attempt =
+            download_attempt(window, client, url, &mut file, &mut transfer, &mut control).await;
+
+        match attempt {
+            DownloadAttempt::Completed => break,
+            DownloadAttempt::Paused => {
+                // Wait for download to be resumed (the control channel only \
+                //   closes once the download is gone)
+                control
+                    .wait_for(|control| *control == DownloadControl::Run)
+                    .await
+                    .map_err(|_| DownloadError::Cancelled)?;
+
+                retries = 0;
+            }
+            DownloadAttempt::Failed(DownloadFailure::Transient(error)) => {
+                // Any progress made resets the retry budget
+                if transfer.progress.downloaded_bytes > downloaded_bytes_before {
+                    retries = 0;
+                }
+
+                // Report last failure once out of retries
+                if retries >= RETRY_MAXIMUM {
+                    return Err(error);
+                }
+
+                sleep(retry_delay(retries)).await;
+
+                retries += 1;
+            }
+            DownloadAttempt::Failed(DownloadFailure::Fatal(error)) => return Err(error),
+        }
+    }
+
+    // Flush downloaded file on disk
+    file.flush()
+        .await
+        .map_err(|error| DownloadError::from_io_error(&error, DownloadError::CouldNotWriteFile))?;
+    file.sync_all()
+        .await
+        .map_err(|error| DownloadError::from_io_error(&error, DownloadError::CouldNotWriteFile))?;
+
+    // Verify decrypted file authenticity (if download is encrypted)
+    if let Some(ref decryptor) = transfer.decryptor {
+        if !decryptor.finalize() {
+            return Err(DownloadError::DecryptionFailed);
+        }
+    }
+
+    // Verify downloaded file integrity (against expected hashes and size)
+    if !transfer.digest.verify() {
+        return Err(DownloadError::IntegrityMismatch);
+    }
+
+    Ok(())
+}
+
+async fn remember_resumption<R: Runtime>(
+    window: &Window<R>,
+    id: u64,
+    update: impl FnOnce(&mut DownloadResumption),
+) {
+    // Store how to resume download in history (the download state is not \
+    //   available when downloading outside of the plugin, eg. in tests)
+    if let Some(state) = window.try_state::<DownloadState>() {
+        state.history.lock().unwrap().update(id, |record| {
+            update(record.resumption.get_or_insert_with(Default::default))
+        });
+
+        state.persist_history().await;
+    }
+}
+
+async fn digest_file(path: &Path, digest: &mut DownloadDigest) -> Option<()> {
+    let mut file = File::open(path).await.ok()?;
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        match file.read(&mut buffer).await.ok()? {
+            0 => return Some(()),
+            size => digest.update(&buffer[..size]),
+        }
+    }
+}
+
+async fn is_resumable(
+    state: &DownloadState,
+    record: &DownloadRecord,
+    resumption: &DownloadResumption,
+) -> bool {
+    // Downloads can only be resumed if partial data is left, and if the \
+    //   server supports ranges and still serves the same file (encrypted \
+    //   downloads and downloads requested with headers cannot be resumed, \
+    //   as their key and headers never get stored)
+    let validator = match DownloadValidator::from_resumption(resumption) {
+        Some(validator) => validator,
+        None => return false,
+    };
+
+    if FileEncryption::is_encrypted_url(&record.url) || resumption.secret_headers {
+        return false;
+    }
+
+    let part_size = fs::metadata(part_path(Path::new(&resumption.path)))
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    if part_size == 0 {
+        return false;
+    }
+
+    let client = match state.client(record.account.as_deref()) {
+        Ok(client) => client,
+        Err(_) => return false,
+    };
+
+    let request_headers = resumption_headers(state, resumption);
+
+    match probe_headers(&client, &record.url, &request_headers).await {
+        Some(headers) => {
+            let accepts_ranges = headers
+                .get(ACCEPT_RANGES)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().eq_ignore_ascii_case("bytes"))
+                .unwrap_or(false);
+
+            accepts_ranges && DownloadValidator::from_headers(&headers) == validator
+        }
+        None => false,
+    }
+}
+
+fn resumption_headers(state: &DownloadState, resumption: &DownloadResumption) -> HeaderMap {
+    // Request resumed downloads with the user agent they were started with \
+    //   (if known, otherwise with the current one)
+    let mut request_headers = HeaderMap::new();
+
+    let user_agent = resumption
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| state.user_agent());
+
+    if let Ok(user_agent) = HeaderValue::from_str(&user_agent) {
+        request_headers.insert(USER_AGENT, user_agent);
+    }
+
+    request_headers
+}
+
+async fn discard_partial(resumption: &DownloadResumption) {
+    let download_path = Path::new(&resumption.path);
+
+    fs::remove_file(part_path(download_path)).await.ok();
+
+    // Release the reserved filename (only if it still is an empty \
+    //   placeholder, as the download may have completed right before the \
+    //   application quit)
+    if resumption.reserved
+        && fs::metadata(download_path)
+            .await
+            .map(|metadata| metadata.len() == 0)
+            .unwrap_or(false)
+    {
+        fs::remove_file(download_path).await.ok();
+    }
+}
+
+fn control_download(
+    state: &DownloadState,
+    id: u64,
+    control: DownloadControl,
+) -> Result<(), DownloadError> {
+    let downloads = state.downloads.lock().unwrap();
+    let download = downloads
+        .get(&id)
+        .ok_or(DownloadError::DownloadDoesNotExist)?;
+
+    // Notice: a cancelled download cannot be paused or resumed anymore.
+    download.send_if_modified(|current| {
+        if *current != DownloadControl::Cancel && *current != control {
+            *current = control;
+
+            true
+        } else {
+            false
+        }
+    });
+
+    Ok(())
+}
+
+async fn file_checksum(path: &Path) -> Option<String> {
+    // Compute SHA-256 checksum of file (encoded in hexadecimal)
+    let mut file = File::open(path).await.ok()?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        match file.read(&mut buffer).await.ok()? {
+            0 => break,
+            size => hasher.update(&buffer[..size]),
+        }
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(target_os = "linux")]
+async fn linux_withhold_execution(file: &File) -> Result<(), std::io::Error> {
+    // Downloaded files never are executable, until the user allows it
+    let mut permissions = file.metadata().await?.permissions();
+
+    permissions.set_mode(permissions.mode() & !0o111);
+
+    file.set_permissions(permissions).await
+}
+
+#[cfg(target_os = "linux")]
+fn linux_allow_execution(path: &Path) -> Result<(), std::io::Error> {
+    // Allow execution to whoever is allowed to read the file
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(target_os = "linux")]
+fn linux_set_origin(file: &File, url: &str, sender: Option<&str>) {
+    // This method records where the file comes from, using the attributes \
+    //   defined by freedesktop.org (so that file managers can show them); \
+    //   this is best-effort, as not all filesystems support extended \
+    //   attributes (eg. FAT32 or some network filesystems)
+    // @ref: https://www.freedesktop.org/wiki/CommonExtendedAttributes/
+    use std::ffi::{c_void, CString};
+    use std::os::fd::AsRawFd;
+
+    // Never leak fragments (which may hold key material)
+    let url = url.split('#').next().unwrap_or(url);
+
+    let mut attributes = vec![("user.xdg.origin.url", url.to_string())];
+
+    if let Some(sender) = sender {
+        attributes.push(("user.xdg.referrer.url", format!("xmpp:{}", sender)));
+        attributes.push((XATTR_SENDER_NAME, sender.to_string()));
+    }
+
+    for (name, value) in attributes {
+        let name = CString::new(name).unwrap();
+
+        // Set attribute on file
+        let return_value = unsafe {
+            libc::fsetxattr(
+                file.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            )
+        };
+
+        if return_value != 0 {
+            let error = std::io::Error::last_os_error();
+
+            // Filesystem does not support extended attributes? Stop there.
+            if error.raw_os_error() == Some(libc::ENOTSUP) {
+                break;
+            }
+
+            log::warn!(
+                "Could not set attribute {:?} on downloaded file: {}",
+                name,
+                error
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn mac_set_quarantine(file: &File, application: &str) -> Result<(), std::io::Error> {
+    // This method sets the quarantine flag so that the user cannot just open \
+    //   downloaded file if it is an application
+    // @ref: https://apple.stackexchange.com/questions/256625/\
+    //   how-to-set-restore-the-com-apple-quarantine-attribute
+    use std::ffi::{c_void, CString};
+    use std::os::fd::AsRawFd;
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    // Acquire current timestamp
+    let now_timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let value = format!(
+        "0081;{:x};{};{}",
+        now_timestamp,
+        application,
+        Uuid::new_v4()
+    );
+
+    let name = CString::new("com.apple.quarantine").unwrap();
+    let value = value.as_bytes();
+    let len = value.len();
+    let value = value.as_ptr() as *const c_void;
+
+    // Set quarantine flag on file
+    let return_value = unsafe {
+        libc::fsetxattr(
+            file.as_raw_fd(),
+            name.as_ptr(),
+            value,
+            len,
+            0,
+            libc::XATTR_CREATE,
+        )
+    };
+
+    // Notice: an existing quarantine flag is kept as-is, as it records when \
+    //   the file was first downloaded
+    if return_value != 0 {
+        let error = std::io::Error::last_os_error();
+
+        if error.raw_os_error() != Some(libc::EEXIST) {
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/**************************************************************************
+ * COMMANDS
+ * ************************************************************************* */
+
+async fn run_download<R: Runtime>(
+    window: &Window<R>,
+    state: &DownloadState,
+    mut record: DownloadRecord,
+    mut request_headers: HeaderMap,
+    ask: bool,
+    automatic: bool,
+    resumption: Option<DownloadResumption>,
+) -> Result<String, DownloadError> {
+    let id = record.id;
+
+    // Register download control (used to pause, resume and cancel) before \
+    //   anything else, so that a download which identifier is already in use \
+    //   gets rejected before it could alter the other download state
+    let (control_tx, control_rx) = watch::channel(DownloadControl::Run);
+    let mut cancel_rx = control_rx.clone();
+
+    let registration = match state.downloads.lock().unwrap().entry(id) {
+        Entry::Occupied(_) => return Err(DownloadError::DownloadAlreadyExists),
+        Entry::Vacant(entry) => {
+            entry.insert(control_tx);
+
+            DownloadRegistration { state, id }
+        }
+    };
+
+    // Acquire expected integrity (if any)
+    let integrity = DownloadIntegrity::new(&record.hashes, record.expected_size)
+        .ok_or(DownloadError::InvalidIntegrity)?;
+
+    // Acquire encryption key (if any); encrypted URLs hold key material, \
+    //   which is never stored in the history (it is kept in memory instead, \
+    //   so that the download can be retried until the application quits)
+    let (url, encryption) = if FileEncryption::is_encrypted_url(&record.url) {
+        let (url, encryption) =
+            FileEncryption::from_url(&record.url).ok_or(DownloadError::InvalidEncryptionKey)?;
+
+        state
+            .secret_urls
+            .lock()
+            .unwrap()
+            .insert(id, record.url.clone());
+
+        record.url = FileEncryption::redact_url(&record.url);
+
+        (url, Some(encryption))
+    } else {
+        (record.url.clone(), None)
+    };
+
+    // Acquire HTTP client (sharing cookies with other downloads of the same \
+    //   account) and request headers; request headers may hold credentials, \
+    //   which are never stored in the history either
+    let client = state.client(record.account.as_deref())?;
+
+    if !request_headers.is_empty() {
+        state
+            .secret_headers
+            .lock()
+            .unwrap()
+            .insert(id, request_headers.clone());
+    }
+
+    // Notice: restored downloads keep the user agent they were started with
+    if !request_headers.contains_key(USER_AGENT) {
+        request_headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&state.user_agent()).map_err(|_| DownloadError::InvalidHeader)?,
+        );
+    }
+
+    // Resolve download destination (asking user where to save file, if \
+    //   requested; which cancels the download if user does not pick a file)
+    let metered_threshold = state.settings.lock().unwrap().values.metered_threshold;
+
+    let defer_threshold = match metered_threshold {
+        Some(threshold) if automatic && network::is_metered().await => Some(threshold),
+        _ => None,
+    };
+
+    let headers = if is_unnamed(&record.filename)
+        || (defer_threshold.is_some() && record.expected_size.is_none())
+    {
+        probe_headers(&client, &url, &request_headers).await
+    } else {
+        None
+    };
+
+    record.filename = resolve_filename(&record.url, &record.filename, headers.as_ref());
+
+    // Defer automatic downloads larger than allowed on metered connections \
+    //   (downloads of unknown size are deferred as well, since they could \
+    //   be of any size); they can later be retried by the user
+    if let Some(threshold) = defer_threshold {
+        let size = record.expected_size.or_else(|| {
+            headers
+                .as_ref()
+                .and_then(|headers| headers.get(CONTENT_LENGTH))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+        });
+
+        if size.map(|size| size > threshold).unwrap_or(true) {
+            record.status = DownloadStatus::Deferred;
+
+            state.history.lock().unwrap().insert(record);
+            state.persist_history().await;
+
+            return Err(DownloadError::Deferred);
+        }
+    }
+
+    let destination = match resumption {
+        Some(ref resumption) if resumption.reserved => {
+            DownloadDestination::Reserved(PathBuf::from(&resumption.path))
+        }
+        Some(ref resumption) => DownloadDestination::Path(PathBuf::from(&resumption.path)),
+        None if ask => ask_save_path(window, &state.download_directory()?, &record.filename)
+            .await
+            .map(DownloadDestination::Path)
+            .ok_or(DownloadError::Cancelled)?,
+        None => {
+            DownloadDestination::Directory(state.download_directory()?, record.filename.clone())
+        }
+    };
+
+    // Record download in history (as queued)
+    let mut transfer = DownloadTransfer::new(id, &integrity, encryption.as_ref());
+
+    transfer.maximum_size = state.settings.lock().unwrap().values.maximum_size;
+    transfer.sender = record.sender.clone();
+    transfer.headers = request_headers;
+
+    // Apply bandwidth limits (the global one, and the one of this download \
+    //   that can be changed while downloading)
+    let throttle = Arc::new(DownloadThrottle::default());
+
+    state.throttles.lock().unwrap().insert(id, throttle.clone());
+
+    transfer.throttles = vec![state.throttle.clone(), throttle];
+
+    // Resume from partial data left by an interrupted download (if any); \
+    //   the digest must cover partial data for the download to be resumed
+    if let Some(ref resumption) = resumption {
+        transfer.validator = DownloadValidator::from_resumption(resumption);
+
+        digest_file(
+            &part_path(Path::new(&resumption.path)),
+            &mut transfer.digest,
+        )
+        .await;
+    }
+
+    state.history.lock().unwrap().insert(record);
+
+    state.persist_history().await;
+
+    // Wait for a free download slot, then download file (aborting download \
+    //   if it gets cancelled, either while queued or while downloading)
+    let result = tokio::select! {
+        result = async {
+            // Notice: the queue is never closed, although treat it as a \
+            //   cancellation if it ever were
+            let _permit = state.queue.acquire().await.map_err(|_| DownloadError::Cancelled)?;
+
+            state.set_status(id, match *control_rx.borrow() {
+                DownloadControl::Pause => DownloadStatus::Paused,
+                _ => DownloadStatus::Downloading,
+            });
+
+            window
+                .emit("download:started", EventDownloadStarted { id })
+                .unwrap();
+
+            download_file(window, &client, &url, destination, transfer, control_rx).await
+        } => result,
+        _ = cancel_rx.wait_for(|control| *control == DownloadControl::Cancel) => {
+            Err(DownloadError::Cancelled)
+        }
+    };
+
+    drop(registration);
+
+    // Download is over, it does not need to be resumed anymore
+    state
+        .history
+        .lock()
+        .unwrap()
+        .update(id, |record| record.resumption = None);
+
+    // Record download outcome in history
+    match result {
+        Ok(ref download_path) => {
+            let path = Path::new(download_path);
+
+            let size = fs::metadata(path)
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+
+            let checksum = file_checksum(path).await;
+            let canonical_path = path.canonicalize().ok();
+
+            // Flag files that could be run (eg. executables and scripts), \
+            //   whose execution is withheld until the user allows it
+            let warning = FileKind::dangerous(
+                &read_head(path).await,
+                &path
+                    .file_name()
+                    .map(|filename| filename.to_string_lossy())
+                    .unwrap_or_default(),
+            );
+
+            {
+                let mut history = state.history.lock().unwrap();
+
+                history.complete(id, download_path.to_owned(), canonical_path, size, checksum);
+                history.update(id, |record| record.warning = warning);
+            }
+
+            window
+                .emit(
+                    "download:completed",
+                    EventDownloadCompleted {
+                        id,
+                        path: download_path.to_owned(),
+                        size,
+                    },
+                )
+                .unwrap();
+
+            if let Some(kind) = warning {
+                window
+                    .emit(
+                        "download:warning",
+                        EventDownloadWarning {
+                            id,
+                            path: download_path.to_owned(),
+                            kind,
+                        },
+                    )
+                    .unwrap();
+            }
+        }
+        Err(DownloadError::Cancelled) => {
+            state.set_status(id, DownloadStatus::Cancelled);
+
+            window
+                .emit("download:cancelled", EventDownloadCancelled { id })
+                .unwrap();
+        }
+        Err(ref error) => {
+            state.set_status(id, DownloadStatus::Failed);
+
+            window
+                .emit(
+                    "download:failed",
+                    EventDownloadFailed {
+                        id,
+                        reason: error.clone(),
+                    },
+                )
+                .unwrap();
+        }
+    }
+
+    state.persist_history().await;
+
+    result
+}
+
+async fn download_file<R: Runtime>(
+    window: &Window<R>,
+    client: &Client,
+    url: &str,
+    destination: DownloadDestination,
+    transfer: DownloadTransfer,
+    control: watch::Receiver<DownloadControl>,
+) -> Result<String, DownloadError> {
+    let (download_path, placeholder) = match destination {
+        DownloadDestination::Directory(download_dir, filename) => {
+            // Generate unique filename (if it already exists, otherwise do \
+            //   not change); partial files of other downloads also are \
+            //   taken into account
+            let download_path = allocate_path(&download_dir, &filename)?;
+
+            (download_path.clone(), Some(download_path))
+        }
+        DownloadDestination::Path(download_path) => {
+            // Path was explicitly picked by the user, who already confirmed \
+            //   overwriting any existing file
+            (download_path, None)
+        }
+        DownloadDestination::Reserved(download_path) => {
+            // Filename was reserved by an interrupted download, which is now \
+            //   being resumed
+            (download_path.clone(), Some(download_path))
+        }
+    };
+
+    // Remember where partial data goes and how it was requested, so that \
+    //   the download can be resumed should the application quit while \
+    //   downloading (request headers other than the user agent are never \
+    //   stored, as they may hold credentials)
+    let reserved = placeholder.is_some();
+
+    let user_agent = transfer
+        .headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let secret_headers = transfer.headers.keys().any(|name| name != USER_AGENT);
+
+    remember_resumption(window, transfer.progress.id, |resumption| {
+        resumption.path = download_path.to_string_lossy().to_string();
+        resumption.reserved = reserved;
+        resumption.user_agent = user_agent;
+        resumption.secret_headers = secret_headers;
+    })
+    .await;
+
+    let part_path = part_path(&download_path);
+
+    // Download file to partial file (removing partial file and reserved \
+    //   filename if download failed, or if it gets cancelled)
+    let part_guard = PartFileGuard {
+        path: Some(part_path.clone()),
+        placeholder,
+    };
+
+    download_to_part(window, client, url, &part_path, transfer, control).await?;
+
+    // Move partial file to its final location (atomically); the partial \
+    //   file and reserved filename are only kept once moved there
+    fs::rename(&part_path, &download_path)
+        .await
+        .map_err(|error| DownloadError::from_io_error(&error, DownloadError::CouldNotMoveFile))?;
+
+    part_guard.disarm();
+
+    // Bounce Dock icon for Downloads folder
+    #[cfg(target_os = "macos")]
+    {
+        notifications::misc::make_download_bounce(download_path.to_string_lossy().as_ref());
+    }
+
+    Ok(download_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn file<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, DownloadState>,
+    id: u64,
+    url: &str,
+    filename: &str,
+    options: Option<DownloadOptions>,
+) -> Result<String, DownloadError> {
+    let options = options.unwrap_or_default();
+
+    let mut record = DownloadRecord::new(id, url, filename);
+
+    // Only keep valid sender addresses (as they get stored on the file)
+    record.sender = options
+        .sender
+        .and_then(|sender| BareJid::new(&sender).ok())
+        .map(|jid| jid.to_string());
+
+    record.account = options
+        .account
+        .map(|account| BareJid::new(&account).map_err(|_| DownloadError::InvalidAccount))
+        .transpose()?
+        .map(|jid| jid.to_string());
+
+    record.hashes = options.hashes.unwrap_or_default();
+    record.expected_size = options.size;
+
+    let request_headers = build_headers(&options.headers.unwrap_or_default())?;
+
+    run_download(
+        &window,
+        &state,
+        record,
+        request_headers,
+        options.ask_location.unwrap_or(false),
+        options.automatic.unwrap_or(false),
+        None,
+    )
+    .await
+}
+
+#[tauri::command]
+pub fn pause(id: u64, state: State<'_, DownloadState>) -> Result<(), DownloadError> {
+    control_download(&state, id, DownloadControl::Pause)?;
+
+    state.history.lock().unwrap().update(id, |record| {
+        if record.status == DownloadStatus::Downloading {
+            record.status = DownloadStatus::Paused;
+        }
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn resume(id: u64, state: State<'_, DownloadState>) -> Result<(), DownloadError> {
+    control_download(&state, id, DownloadControl::Run)?;
+
+    state.history.lock().unwrap().update(id, |record| {
+        if record.status == DownloadStatus::Paused {
+            record.status = DownloadStatus::Downloading;
+        }
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel(id: u64, state: State<'_, DownloadState>) -> Result<(), DownloadError> {
+    control_download(&state, id, DownloadControl::Cancel)
+}
+
+#[tauri::command]
+pub fn list(state: State<'_, DownloadState>) -> Vec<DownloadRecord> {
+    state.history.lock().unwrap().list()
+}
+
+#[tauri::command]
+pub async fn retry<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, DownloadState>,
+    id: u64,
+) -> Result<String, DownloadError> {
+    // Only failed, cancelled or deferred downloads can be retried
+    let record = state
+        .history
+        .lock()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or(DownloadError::DownloadDoesNotExist)?;
+
+    match record.status {
+        DownloadStatus::Failed | DownloadStatus::Cancelled | DownloadStatus::Deferred => {
+            let url = state
+                .secret_urls
+                .lock()
+                .unwrap()
+                .get(&id)
+                .cloned()
+                .unwrap_or(record.url);
+
+            let mut retry_record = DownloadRecord::new(id, &url, &record.filename);
+
+            retry_record.hashes = record.hashes;
+            retry_record.expected_size = record.expected_size;
+            retry_record.sender = record.sender;
+            retry_record.account = record.account;
+
+            let request_headers = state
+                .secret_headers
+                .lock()
+                .unwrap()
+                .get(&id)
+                .cloned()
+                .unwrap_or_default();
+
+            run_download(
+                &window,
+                &state,
+                retry_record,
+                request_headers,
+                false,
+                false,
+                None,
+            )
+            .await
+        }
+        _ => Err(DownloadError::CannotRetry),
+    }
+}
+
+#[tauri::command]
+pub async fn restore<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, DownloadState>,
+) -> Result<(), DownloadError> {
+    // Restore downloads that got interrupted when the application last quit, \
+    //   either resuming them or discarding their partial data. Notice: this \
+    //   is requested by the front-end once it listens for events, otherwise \
+    //   restoration results would get lost.
+    let interrupted = state.history.lock().unwrap().take_interrupted();
+
+    if interrupted.is_empty() {
+        return Ok(());
+    }
+
+    state.persist_history().await;
+
+    for (record, resumption) in interrupted {
+        let resumed = is_resumable(&state, &record, &resumption).await;
+
+        if !resumed {
+            discard_partial(&resumption).await;
+        }
+
+        window
+            .emit(
+                "download:restored",
+                EventDownloadRestored {
+                    id: record.id,
+                    resumed,
+                },
+            )
+            .unwrap();
+
+        if resumed {
+            let window = window.clone();
+
+            let mut resume_record = DownloadRecord::new(record.id, &record.url, &record.filename);
+
+            resume_record.hashes = record.hashes;
+            resume_record.expected_size = record.expected_size;
+            resume_record.sender = record.sender;
+            resume_record.account = record.account;
+
+            let request_headers = resumption_headers(&state, &resumption);
+
+            tauri::async_runtime::spawn(async move {
+                let state = window.state::<DownloadState>();
+
+                run_download(
+                    &window,
+                    &state,
+                    resume_record,
+                    request_headers,
+                    false,
+                    false,
+                    Some(resumption),
+                )
+                .await
+                .ok();
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn clear_history(state: State<'_, DownloadState>) -> Result<(), DownloadError> {
+    state.history.lock().unwrap().clear();
+
+    state.persist_history().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_directory(state: State<'_, DownloadState>) -> Result<String, DownloadError> {
+    state
+        .download_directory()
+        .map(|directory| directory.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn set_directory(
+    state: State<'_, DownloadState>,
+    directory: Option<String>,
+) -> Result<(), DownloadError> {
+    // Any provided directory must exist (passing none restores the default)
+    let directory = directory.map(PathBuf::from);
+
+    if let Some(ref directory) = directory {
+        if !directory.is_absolute() || !directory.is_dir() {
+            return Err(DownloadError::InvalidDirectory);
+        }
+    }
+
+    state.settings.lock().unwrap().values.directory = directory;
+
+    state.persist_settings().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn choose_directory<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, DownloadState>,
+) -> Result<Option<String>, DownloadError> {
+    // Ask user to pick the default download directory (nothing changes if \
+    //   the user dismisses the dialog)
+    let Some(directory) = ask_directory(&window, &state.download_directory()?).await else {
+        return Ok(None);
+    };
+
+    state.settings.lock().unwrap().values.directory = Some(directory.clone());
+
+    state.persist_settings().await;
+
+    Ok(Some(directory.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn get_maximum_size(state: State<'_, DownloadState>) -> Option<u64> {
+    state.settings.lock().unwrap().values.maximum_size
+}
+
+#[tauri::command]
+pub async fn set_maximum_size(
+    state: State<'_, DownloadState>,
+    size: Option<u64>,
+) -> Result<(), DownloadError> {
+    // Passing no size removes the limit
+    state.settings.lock().unwrap().values.maximum_size = size;
+
+    state.persist_settings().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_rate_limit(state: State<'_, DownloadState>) -> Option<u64> {
+    state.throttle.rate()
+}
+
+#[tauri::command]
+pub async fn set_rate_limit(
+    state: State<'_, DownloadState>,
+    rate: Option<u64>,
+) -> Result<(), DownloadError> {
+    // Passing no rate removes the limit (applies to ongoing downloads as well)
+    state.throttle.set_rate(rate);
+    state.settings.lock().unwrap().values.rate_limit = state.throttle.rate();
+
+    state.persist_settings().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_download_rate_limit(
+    state: State<'_, DownloadState>,
+    id: u64,
+    rate: Option<u64>,
+) -> Result<(), DownloadError> {
+    // Only ongoing downloads can be limited (this limit is not persisted)
+    state
+        .throttles
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or(DownloadError::DownloadDoesNotExist)?
+        .set_rate(rate);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_metered_threshold(state: State<'_, DownloadState>) -> Option<u64> {
+    state.settings.lock().unwrap().values.metered_threshold
+}
+
+#[tauri::command]
+pub async fn set_metered_threshold(
+    state: State<'_, DownloadState>,
+    size: Option<u64>,
+) -> Result<(), DownloadError> {
+    // Passing no size never defers automatic downloads
+    state.settings.lock().unwrap().values.metered_threshold = size;
+
+    state.persist_settings().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_user_agent(state: State<'_, DownloadState>) -> String {
+    state.user_agent()
+}
+
+#[tauri::command]
+pub async fn set_user_agent(
+    state: State<'_, DownloadState>,
+    user_agent: Option<String>,
+) -> Result<(), DownloadError> {
+    // Passing no user agent restores the default one (which identifies the \
+    //   application version)
+    if let Some(ref user_agent) = user_agent {
+        HeaderValue::from_str(user_agent).map_err(|_| DownloadError::InvalidHeader)?;
+    }
+
+    state.settings.lock().unwrap().values.user_agent = user_agent;
+
+    state.persist_settings().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn clear_cookies(state: State<'_, DownloadState>, account: &str) -> Result<(), DownloadError> {
+    // Forget the cookie jar of the account (eg. when it gets signed out)
+    let account = BareJid::new(account).map_err(|_| DownloadError::InvalidAccount)?;
+
+    state
+        .account_clients
+        .lock()
+        .unwrap()
+        .remove(&account.to_string());
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn allow_execution(
+    state: State<'_, DownloadState>,
+    id: u64,
+) -> Result<(), DownloadError> {
+    // Only completed downloads that were flagged can be allowed to run
+    let path = {
+        let history = state.history.lock().unwrap();
+        let record = history.get(id).ok_or(DownloadError::DownloadDoesNotExist)?;
+
+        match (record.status, record.warning, &record.path) {
+            (DownloadStatus::Completed, Some(_), Some(path)) => PathBuf::from(path),
+            _ => return Err(DownloadError::CannotAllowExecution),
+        }
+    };
+
+    if !path.exists() {
+        return Err(DownloadError::CannotAllowExecution);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        linux_allow_execution(&path).map_err(|_| DownloadError::CannotAllowExecution)?;
+    }
+
+    state
+        .history
+        .lock()
+        .unwrap()
+        .update(id, |record| record.warning = None);
+
+    state.persist_history().await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn reveal(id: u64, state: State<'_, DownloadState>) -> Result<(), DownloadError> {
+    // Only completed downloads which file still exists can be revealed
+    let path = {
+        let history = state.history.lock().unwrap();
+
+        if history.get(id).is_none() {
+            return Err(DownloadError::DownloadDoesNotExist);
+        }
+
+        history
+            .get_completed(id)
+            .and_then(|record| record.path.clone())
+            .map(PathBuf::from)
+            .ok_or(DownloadError::CannotReveal)?
+    };
+
+    if !path.exists() {
+        return Err(DownloadError::CannotReveal);
+    }
+
+    opener::reveal(&path)
+        .await
+        .map_err(|_| DownloadError::CannotReveal)
+}
+
+#[tauri::command]
+pub async fn reveal_in_folder(
+    state: State<'_, DownloadState>,
+    path: &str,
+) -> Result<(), DownloadError> {
+    // Only files of completed downloads can be revealed (the webview must \
+    //   never be able to probe arbitrary paths)
+    let canonical_path = Path::new(path)
+        .canonicalize()
+        .or(Err(DownloadError::DownloadDoesNotExist))?;
+
+    let path = state
+        .history
+        .lock()
+        .unwrap()
+        .find_completed(&canonical_path)
+        .and_then(|record| record.path.clone())
+        .map(PathBuf::from)
+        .ok_or(DownloadError::DownloadDoesNotExist)?;
+
+    opener::reveal(&path)
+        .await
+        .map_err(|_| DownloadError::CannotReveal)
+}
+
+#[tauri::command]
+pub async fn open_file(state: State<'_, DownloadState>, path: &str) -> Result<(), DownloadError> {
+    // Only files of completed downloads can be opened, and only once their \
+    //   execution was allowed (if they were flagged as potentially \
+    //   dangerous), as opening a file may run it
+    let canonical_path = Path::new(path)
+        .canonicalize()
+        .or(Err(DownloadError::DownloadDoesNotExist))?;
+
+    let path = {
+        let history = state.history.lock().unwrap();
+
+        let record = history
+            .find_completed(&canonical_path)
+            .ok_or(DownloadError::DownloadDoesNotExist)?;
+
+        match (record.warning, &record.path) {
+            (None, Some(path)) => PathBuf::from(path),
+            _ => return Err(DownloadError::CannotOpen),
+        }
+    };
+
+    opener::open(&path)
+        .await
+        .map_err(|_| DownloadError::CannotOpen)
+}
+
+/**************************************************************************
+ * PROVIDERS
+ * ************************************************************************* */
+
+pub fn provide<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("download")
+        .invoke_handler(tauri::generate_handler![
+            file,
+            pause,
+            resume,
+            cancel,
+            list,
+            retry,
+            restore,
+            clear_history,
+            reveal,
+            reveal_in_folder,
+            open_file,
+            get_directory,
+            set_directory,
+            choose_directory,
+            get_maximum_size,
+            set_maximum_size,
+            get_rate_limit,
+            set_rate_limit,
+            set_download_rate_limit,
+            get_metered_threshold,
+            set_metered_threshold,
+            get_user_agent,
+            set_user_agent,
+            clear_cookies,
+            allow_execution
+        ])
+        .setup(|app_handle, _| {
+            app_handle.manage(DownloadState::new(
+                app_handle.path().app_data_dir().ok(),
+                &app_handle.package_info().version.to_string(),
+            ));
+
+            Ok(())
+        })
+        .build()
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        list_directory, make_directory, make_window, serve_http, serve_http_with,
+    };
+    use reqwest::dns::{Name, Resolve, Resolving};
+    use tauri::test::MockRuntime;
+    use tokio::net::TcpListener;
+
+    const TEST_FILE_CONTENT: &[u8] = b"%PDF-1.7 report";
+
+    async fn download_to_directory(
+        window: &Window<MockRuntime>,
+        client: &Client,
+        id: u64,
+        url: &str,
+        directory: &Path,
+        maximum_size: Option<u64>,
+    ) -> Result<String, DownloadError> {
+        let (_control_tx, control_rx) = watch::channel(DownloadControl::Run);
+        let mut transfer = DownloadTransfer::new(id, &DownloadIntegrity::default(), None);
+
+        transfer.maximum_size = maximum_size;
+
+        download_file(
+            window,
+            client,
+            url,
+            DownloadDestination::Directory(directory.to_path_buf(), "report.pdf".to_string()),
+            transfer,
+            control_rx,
+        )
+        .await
+    }
 
     #[test]
     fn test_split_filename() {
@@ -304,36 +2465,755 @@ mod tests {
     }
 
     #[test]
-    fn test_path_traversal() {
-        assert_eq!(remove_path_traversal("file"), "file");
-        assert_eq!(remove_path_traversal("file.txt"), "file.txt");
-        assert_eq!(remove_path_traversal("file.tar.gz"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz/"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz\\"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz:"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz~"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz@"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz//"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz\\\\"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz::"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz~~"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz@@"), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz.."), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz...."), "file.tar.gz");
-        assert_eq!(remove_path_traversal("file.tar.gz..\\.."), "file.tar.gz");
-        assert_eq!(
-            remove_path_traversal("C:\\file.tar.gz..//.."),
-            "Cfile.tar.gz"
-        );
-        assert_eq!(remove_path_traversal("~/file.tar.gz..:.."), "file.tar.gz");
-        assert_eq!(
-            remove_path_traversal("../../../../file.tar.gz..~~.."),
-            "file.tar.gz"
-        );
-        assert_eq!(remove_path_traversal("/file.tar.gz..@@.."), "file.tar.gz");
-        assert_eq!(remove_path_traversal("/./."), "");
-        assert_eq!(remove_path_traversal("/.../..."), "");
-        assert_eq!(remove_path_traversal("\x00hi"), "hi");
-        assert_eq!(remove_path_traversal("🤰🏽¨¬ø¡你好"), "🤰🏽¨¬ø¡你好");
+    fn test_resolve_filename() {
+        let make_headers = |headers: &[(HeaderName, &str)]| {
+            headers
+                .iter()
+                .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+                .collect::<HeaderMap>()
+        };
+
+        let disposition = make_headers(&[
+            (
+                CONTENT_DISPOSITION,
+                "attachment; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf",
+            ),
+            (CONTENT_TYPE, "application/pdf"),
+        ]);
+        let image = make_headers(&[(CONTENT_TYPE, "image/jpeg")]);
+        let url = "https://upload.prose.org/a1b2c3d4?token=secret";
+
+        // Provided filename always wins
+        assert_eq!(
+            resolve_filename(url, "photo.png", Some(&disposition)),
+            "photo.png"
+        );
+
+        // Otherwise, use the server-provided filename, then the URL
+        assert_eq!(
+            resolve_filename(url, "undefined", Some(&disposition)),
+            "résumé.pdf"
+        );
+        assert_eq!(resolve_filename(url, "", Some(&image)), "a1b2c3d4.jpg");
+        assert_eq!(resolve_filename(url, "", None), "a1b2c3d4");
+        assert_eq!(
+            resolve_filename("https://prose.org/file.png", "", Some(&image)),
+            "file.png"
+        );
+
+        // Server-provided filenames are sanitized as well
+        assert_eq!(
+            resolve_filename(
+                url,
+                "",
+                Some(&make_headers(&[(
+                    CONTENT_DISPOSITION,
+                    "attachment; filename=\"../../.bashrc\""
+                )]))
+            ),
+            "bashrc"
+        );
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("/tmp/file.txt")),
+            PathBuf::from("/tmp/file.txt.part")
+        );
+        assert_eq!(
+            part_path(Path::new("/tmp/file.tar.gz")),
+            PathBuf::from("/tmp/file.tar.gz.part")
+        );
+        assert_eq!(part_path(Path::new("file")), PathBuf::from("file.part"));
+    }
+
+    #[test]
+    fn test_progress_advance() {
+        let mut transfer = DownloadTransfer::new(1, &DownloadIntegrity::default(), None);
+
+        // Unknown total size does not clamp progress
+        transfer.progress.advance(100);
+        transfer.progress.advance(50);
+
+        assert_eq!(transfer.progress.downloaded_bytes, 150);
+        assert_eq!(transfer.progress.eta(), None);
+
+        // Known total size clamps progress
+        transfer.progress.total_bytes = Some(200);
+        transfer.progress.advance(100);
+
+        assert_eq!(transfer.progress.downloaded_bytes, 200);
+        assert_eq!(transfer.progress.eta(), Some(0));
+    }
+
+    #[test]
+    fn test_progress_speed() {
+        let mut transfer = DownloadTransfer::new(1, &DownloadIntegrity::default(), None);
+        let start = transfer.progress.last_size_report;
+
+        transfer.progress.total_bytes = Some(10000);
+        transfer.progress.advance(1000);
+        transfer
+            .progress
+            .sample_speed(start + Duration::from_secs(1));
+
+        assert_eq!(transfer.progress.speed, 1000.0);
+        assert_eq!(transfer.progress.eta(), Some(9));
+
+        // Later samples are smoothed
+        transfer.progress.last_size_report = start + Duration::from_secs(1);
+        transfer.progress.last_size_report_bytes = 1000;
+        transfer.progress.advance(2000);
+        transfer
+            .progress
+            .sample_speed(start + Duration::from_secs(2));
+
+        assert_eq!(transfer.progress.speed.round(), 1300.0);
+        assert_eq!(transfer.progress.eta(), Some(6));
+    }
+
+    #[test]
+    fn test_parse_content_range_total() {
+        assert_eq!(parse_content_range_total("bytes 200-999/1000"), Some(1000));
+        assert_eq!(parse_content_range_total(" bytes 0-0/1 "), Some(1));
+        assert_eq!(parse_content_range_total("bytes 200-999/*"), None);
+        assert_eq!(parse_content_range_total("bytes */1000"), Some(1000));
+        assert_eq!(parse_content_range_total("items 0-1/2"), None);
+        assert_eq!(parse_content_range_total(""), None);
+    }
+
+    #[test]
+    fn test_validator_if_range() {
+        let strong = DownloadValidator {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        };
+        let weak = DownloadValidator {
+            etag: Some("W/\"abc\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        };
+        let weak_only = DownloadValidator {
+            etag: Some("W/\"abc\"".to_string()),
+            last_modified: None,
+        };
+
+        assert_eq!(strong.if_range(), Some("\"abc\""));
+        assert_eq!(weak.if_range(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(weak_only.if_range(), None);
+        assert_eq!(DownloadValidator::default().if_range(), None);
+    }
+
+    #[test]
+    fn test_validator_from_resumption() {
+        let mut resumption = DownloadResumption {
+            path: "/tmp/report.pdf".to_string(),
+            reserved: true,
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            user_agent: None,
+            secret_headers: false,
+        };
+
+        assert_eq!(
+            DownloadValidator::from_resumption(&resumption),
+            Some(DownloadValidator {
+                etag: Some("\"abc\"".to_string()),
+                last_modified: None,
+            })
+        );
+
+        // Downloads that cannot be validated cannot be resumed
+        resumption.etag = Some("W/\"abc\"".to_string());
+
+        assert_eq!(DownloadValidator::from_resumption(&resumption), None);
+    }
+
+    #[tokio::test]
+    async fn test_discard_partial() {
+        let directory = make_directory();
+
+        let resumption = |filename: &str| DownloadResumption {
+            path: directory
+                .path()
+                .join(filename)
+                .to_string_lossy()
+                .to_string(),
+            reserved: true,
+            etag: None,
+            last_modified: None,
+            user_agent: None,
+            secret_headers: false,
+        };
+
+        // Interrupted download, with its reserved filename
+        std::fs::write(directory.path().join("a.pdf"), b"").unwrap();
+        std::fs::write(directory.path().join("a.pdf.part"), b"%PDF").unwrap();
+
+        // Download that completed right before the application quit
+        std::fs::write(directory.path().join("b.pdf"), TEST_FILE_CONTENT).unwrap();
+
+        discard_partial(&resumption("a.pdf")).await;
+        discard_partial(&resumption("b.pdf")).await;
+
+        assert_eq!(list_directory(directory.path()), vec!["b.pdf"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_run_download_duplicate_id() {
+        let (_app, window) = make_window();
+        let state = DownloadState::new(None, "1.0.0");
+
+        // A first download is running, which was requested with headers
+        let (control_tx, _control_rx) = watch::channel(DownloadControl::Run);
+
+        let mut headers = HeaderMap::new();
+
+        headers.insert("authorization", HeaderValue::from_static("Bearer first"));
+
+        state.downloads.lock().unwrap().insert(1, control_tx);
+        state
+            .secret_headers
+            .lock()
+            .unwrap()
+            .insert(1, headers.clone());
+        state.history.lock().unwrap().insert(DownloadRecord::new(
+            1,
+            "https://prose.org/first.pdf",
+            "first.pdf",
+        ));
+
+        // Another download with the same identifier is rejected, leaving the \
+        //   first download untouched
+        let mut other_headers = HeaderMap::new();
+
+        other_headers.insert("authorization", HeaderValue::from_static("Bearer other"));
+
+        assert_eq!(
+            run_download(
+                &window,
+                &state,
+                DownloadRecord::new(1, "https://prose.org/other.pdf", "other.pdf"),
+                other_headers,
+                false,
+                false,
+                None,
+            )
+            .await,
+            Err(DownloadError::DownloadAlreadyExists)
+        );
+
+        assert_eq!(state.secret_headers.lock().unwrap().get(&1), Some(&headers));
+        assert_eq!(
+            state.history.lock().unwrap().get(1).unwrap().filename,
+            "first.pdf"
+        );
+        assert!(state.downloads.lock().unwrap().contains_key(&1));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_restore_interrupted() {
+        let (app, window) = make_window();
+        let directory = make_directory();
+
+        // Server serving the rest of a file, which was partially downloaded
+        let (url, requests) = serve_http_with(
+            "206 Partial Content",
+            "Accept-Ranges: bytes\r\nETag: \"abc\"\r\nContent-Range: bytes 4-14/15\r\n",
+            &TEST_FILE_CONTENT[4..],
+        )
+        .await;
+
+        let interrupted = |id: u64, filename: &str, secret_headers: bool| {
+            let path = directory.path().join(filename);
+
+            std::fs::write(part_path(&path), &TEST_FILE_CONTENT[..4]).unwrap();
+
+            let mut record = DownloadRecord::new(id, &url, filename);
+
+            record.status = DownloadStatus::Downloading;
+            record.resumption = Some(DownloadResumption {
+                path: path.to_string_lossy().to_string(),
+                reserved: false,
+                etag: Some("\"abc\"".to_string()),
+                last_modified: None,
+                user_agent: Some("Prose/0.1.0 (test)".to_string()),
+                secret_headers,
+            });
+
+            record
+        };
+
+        // Downloads requested with headers cannot be resumed, as their \
+        //   headers were not stored
+        let records = vec![
+            interrupted(1, "report.pdf", false),
+            interrupted(2, "secret.pdf", true),
+        ];
+
+        std::fs::write(
+            directory.path().join("downloads.json"),
+            serde_json::to_vec(&records).unwrap(),
+        )
+        .unwrap();
+
+        app.manage(DownloadState::new(
+            Some(directory.path().to_path_buf()),
+            "1.0.0",
+        ));
+
+        restore(window.clone(), app.state()).await.unwrap();
+
+        let state = app.state::<DownloadState>();
+
+        for _ in 0..100 {
+            if state.history.lock().unwrap().get(1).unwrap().status == DownloadStatus::Completed {
+                break;
+            }
+
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(
+            std::fs::read(directory.path().join("report.pdf")).unwrap(),
+            TEST_FILE_CONTENT
+        );
+        assert_eq!(
+            list_directory(directory.path()),
+            vec!["downloads.json", "report.pdf"]
+        );
+        assert_eq!(
+            state.history.lock().unwrap().get(2).unwrap().status,
+            DownloadStatus::Failed
+        );
+
+        // Resumed download was requested as it was started
+        let requests = requests.lock().unwrap();
+
+        assert!(requests.len() >= 2);
+        assert!(requests.iter().all(|request| request
+            .to_lowercase()
+            .contains("user-agent: prose/0.1.0 (test)")));
+    }
+
+    #[test]
+    fn test_transient_status() {
+        assert!(is_transient_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_transient_status(StatusCode::BAD_GATEWAY));
+        assert!(is_transient_status(StatusCode::REQUEST_TIMEOUT));
+        assert!(is_transient_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_transient_status(StatusCode::NOT_FOUND));
+        assert!(!is_transient_status(StatusCode::FORBIDDEN));
+        assert!(!is_transient_status(StatusCode::OK));
+    }
+
+    #[test]
+    fn test_allocate_path() {
+        let directory = make_directory();
+
+        std::fs::write(directory.path().join("report.pdf"), b"existing").unwrap();
+        std::fs::write(directory.path().join("report (1).pdf.part"), b"").unwrap();
+
+        assert_eq!(
+            allocate_path(directory.path(), "report.pdf").unwrap(),
+            directory.path().join("report (2).pdf")
+        );
+        assert_eq!(
+            list_directory(directory.path()),
+            vec![
+                "report (1).pdf.part",
+                "report (2).pdf",
+                "report (2).pdf.part",
+                "report.pdf"
+            ]
+        );
+        assert_eq!(
+            std::fs::read(directory.path().join("report.pdf")).unwrap(),
+            b"existing"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_downloads_same_filename() {
+        const DOWNLOADS_COUNT: u64 = 24;
+
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("200 OK", TEST_FILE_CONTENT).await;
+
+        std::fs::write(directory.path().join("report.pdf"), b"existing").unwrap();
+
+        let downloads = (0..DOWNLOADS_COUNT)
+            .map(|id| {
+                let (window, client, url, directory) = (
+                    window.clone(),
+                    client.clone(),
+                    url.clone(),
+                    directory.path().to_path_buf(),
+                );
+
+                tokio::spawn(async move {
+                    download_to_directory(&window, &client, id, &url, &directory, None).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut paths = Vec::new();
+
+        for download in downloads {
+            paths.push(download.await.unwrap().unwrap());
+        }
+
+        paths.sort();
+        paths.dedup();
+
+        // Every download must have landed in its own file, and the file \
+        //   that already existed must not have been overwritten
+        assert_eq!(paths.len(), DOWNLOADS_COUNT as usize);
+        assert_eq!(
+            list_directory(directory.path()).len(),
+            DOWNLOADS_COUNT as usize + 1
+        );
+        assert_eq!(
+            std::fs::read(directory.path().join("report.pdf")).unwrap(),
+            b"existing"
+        );
+
+        for path in paths {
+            assert_eq!(std::fs::read(path).unwrap(), TEST_FILE_CONTENT);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_failed_download_releases_filename() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("404 Not Found", b"").await;
+
+        assert!(
+            download_to_directory(&window, &client, 1, &url, directory.path(), None)
+                .await
+                .is_err()
+        );
+        assert!(list_directory(directory.path()).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_download_maximum_size() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("200 OK", TEST_FILE_CONTENT).await;
+
+        assert_eq!(
+            download_to_directory(&window, &client, 1, &url, directory.path(), Some(4)).await,
+            Err(DownloadError::TooLarge)
+        );
+        assert!(list_directory(directory.path()).is_empty());
+
+        assert!(download_to_directory(
+            &window,
+            &client,
+            2,
+            &url,
+            directory.path(),
+            Some(TEST_FILE_CONTENT.len() as u64)
+        )
+        .await
+        .is_ok());
+    }
+
+    #[test]
+    fn test_build_headers() {
+        let headers = build_headers(&HashMap::from([
+            ("Authorization".to_string(), "Bearer token".to_string()),
+            ("X-Prose-Tenant".to_string(), "prose.org".to_string()),
+        ]))
+        .unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer token");
+
+        for name in ["Range", "Host", "User-Agent"] {
+            assert_eq!(
+                build_headers(&HashMap::from([(name.to_string(), "x".to_string())])),
+                Err(DownloadError::InvalidHeader)
+            );
+        }
+
+        assert_eq!(
+            build_headers(&HashMap::from([(
+                "Authorization".to_string(),
+                "Bearer token\r\nHost: prose.org".to_string()
+            )])),
+            Err(DownloadError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let state = DownloadState::new(None, "1.2.3");
+
+        assert_eq!(
+            state.user_agent(),
+            format!("Prose/1.2.3 ({})", std::env::consts::OS)
+        );
+
+        state.settings.lock().unwrap().values.user_agent = Some("Custom/1.0".to_string());
+
+        assert_eq!(state.user_agent(), "Custom/1.0");
+    }
+
+    #[test]
+    fn test_error_from_io_error() {
+        let error = |kind| {
+            DownloadError::from_io_error(
+                &std::io::Error::from(kind),
+                DownloadError::CouldNotWriteFile,
+            )
+        };
+
+        assert_eq!(error(ErrorKind::StorageFull), DownloadError::DiskFull);
+        assert_eq!(error(ErrorKind::QuotaExceeded), DownloadError::DiskFull);
+        assert_eq!(
+            error(ErrorKind::PermissionDenied),
+            DownloadError::PermissionDenied
+        );
+        assert_eq!(
+            error(ErrorKind::ReadOnlyFilesystem),
+            DownloadError::PermissionDenied
+        );
+        assert_eq!(error(ErrorKind::NotFound), DownloadError::CouldNotWriteFile);
+    }
+
+    #[tokio::test]
+    async fn test_error_from_request_error() {
+        let client = build_client(false).unwrap();
+
+        // Acquire a local port that nothing listens on
+        let address = TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let error = client
+            .get(format!("http://{}/", address))
+            .send()
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            DownloadError::from_request_error(&error),
+            DownloadError::ConnectionFailed
+        );
+
+        // Talk TLS to a server answering in plain text
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+                .await
+                .ok();
+        });
+
+        let error = client.get(url).send().await.unwrap_err();
+
+        assert_eq!(
+            DownloadError::from_request_error(&error),
+            DownloadError::TlsFailed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_from_request_error_dns() {
+        struct UnresolvableResolver;
+
+        impl Resolve for UnresolvableResolver {
+            fn resolve(&self, _: Name) -> Resolving {
+                Box::pin(async {
+                    Err(Box::new(ResolveError(std::io::Error::from(ErrorKind::NotFound))) as _)
+                })
+            }
+        }
+
+        // Resolve through a failing resolver, so that no DNS request is made
+        let client = Client::builder()
+            .dns_resolver(Arc::new(UnresolvableResolver))
+            .build()
+            .unwrap();
+
+        let error = client
+            .get("http://files.prose.invalid/")
+            .send()
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            DownloadError::from_request_error(&error),
+            DownloadError::DnsFailed
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_download_http_status() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("404 Not Found", b"").await;
+
+        assert_eq!(
+            download_to_directory(&window, &client, 1, &url, directory.path(), None).await,
+            Err(DownloadError::HttpStatus { status: 404 })
+        );
+        assert!(list_directory(directory.path()).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_download_rename_failure() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("200 OK", TEST_FILE_CONTENT).await;
+
+        // Final location is taken by a directory, which cannot be replaced
+        std::fs::create_dir(directory.path().join("report.pdf")).unwrap();
+        std::fs::write(directory.path().join("report.pdf").join("keep"), b"").unwrap();
+
+        let (_control_tx, control_rx) = watch::channel(DownloadControl::Run);
+
+        assert!(download_file(
+            &window,
+            &client,
+            &url,
+            DownloadDestination::Path(directory.path().join("report.pdf")),
+            DownloadTransfer::new(1, &DownloadIntegrity::default(), None),
+            control_rx,
+        )
+        .await
+        .is_err());
+
+        // Partial file does not get left behind
+        assert_eq!(list_directory(directory.path()), vec!["report.pdf"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_throttled() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("200 OK", &[0; 1500]).await;
+
+        let (_control_tx, control_rx) = watch::channel(DownloadControl::Run);
+        let mut transfer = DownloadTransfer::new(1, &DownloadIntegrity::default(), None);
+
+        transfer.throttles = vec![
+            Arc::new(DownloadThrottle::new(None)),
+            Arc::new(DownloadThrottle::new(Some(1000))),
+        ];
+
+        // Bucket allows a burst of 1000 bytes, then remaining 500 bytes take \
+        //   half a second (the clock is paused, so that only throttling makes \
+        //   time advance; the bound stays loose, as the response body may get \
+        //   chunked differently across runs)
+        let start = tokio::time::Instant::now();
+
+        let path = download_file(
+            &window,
+            &client,
+            &url,
+            DownloadDestination::Directory(directory.path().to_path_buf(), "data.bin".to_string()),
+            transfer,
+            control_rx,
+        )
+        .await
+        .unwrap();
+
+        assert!((500..1000).contains(&start.elapsed().as_millis()));
+        assert_eq!(std::fs::metadata(path).unwrap().len(), 1500);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_download_withholds_execution() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let url = serve_http("200 OK", b"#!/bin/sh\necho hello").await;
+
+        let path = download_to_directory(&window, &client, 1, &url, directory.path(), None)
+            .await
+            .unwrap();
+
+        let mode = |path: &str| std::fs::metadata(path).unwrap().permissions().mode();
+
+        assert_eq!(mode(&path) & 0o111, 0);
+
+        linux_allow_execution(Path::new(&path)).unwrap();
+
+        assert_eq!(mode(&path) & 0o100, 0o100);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_set_origin() {
+        use std::ffi::{c_void, CString};
+
+        let directory = make_directory();
+        let path = directory.path().join("report.pdf");
+        let file = File::create(&path).await.unwrap();
+
+        linux_set_origin(
+            &file,
+            "https://upload.prose.org/report.pdf#aabbcc",
+            Some("valerian@prose.org"),
+        );
+
+        let read_attribute = |name: &str| {
+            let path = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+            let name = CString::new(name).unwrap();
+            let mut value = vec![0u8; 256];
+
+            let length = unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_mut_ptr() as *mut c_void,
+                    value.len(),
+                )
+            };
+
+            if length < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            value.truncate(length as usize);
+
+            Ok(String::from_utf8(value).unwrap())
+        };
+
+        match read_attribute("user.xdg.origin.url") {
+            Ok(origin) => {
+                assert_eq!(origin, "https://upload.prose.org/report.pdf");
+                assert_eq!(
+                    read_attribute("user.xdg.referrer.url").unwrap(),
+                    "xmpp:valerian@prose.org"
+                );
+                assert_eq!(
+                    read_attribute(XATTR_SENDER_NAME).unwrap(),
+                    "valerian@prose.org"
+                );
+            }
+            Err(error) => {
+                // Temporary directory does not support extended attributes
+                assert_eq!(error.raw_os_error(), Some(libc::ENOTSUP));
+            }
+        }
     }
 }
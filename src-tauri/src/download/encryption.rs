@@ -0,0 +1,484 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use aes::Aes256;
+use ctr::Ctr32BE;
+use ghash::universal_hash::UniversalHash;
+use ghash::{Block, GHash};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const ENCRYPTED_URL_SCHEME: &str = "aesgcm://";
+const DECRYPTED_URL_SCHEME: &str = "https://";
+
+const KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 12;
+const BLOCK_LENGTH: usize = 16;
+
+pub const TAG_LENGTH: usize = 16;
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Clone)]
+pub struct FileEncryption {
+    key: Zeroizing<[u8; KEY_LENGTH]>,
+    iv: Vec<u8>,
+}
+
+#[derive(Clone)]
+struct FileAuthenticator {
+    ghash: GHash,
+    tag_mask: Block,
+    pending: Vec<u8>,
+    length: u64,
+}
+
+pub struct FileDecryptor {
+    encryption: FileEncryption,
+    cipher: Ctr32BE<Aes256>,
+    authenticator: FileAuthenticator,
+    held: Vec<u8>,
+}
+
+pub struct FileEncryptor {
+    cipher: Ctr32BE<Aes256>,
+    authenticator: FileAuthenticator,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl fmt::Debug for FileEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Important: never print out key material (eg. in logs)
+        f.write_str("FileEncryption(<redacted>)")
+    }
+}
+
+impl FileEncryption {
+    pub fn generate() -> Option<Self> {
+        // Generate a random key and IV (using the 96-bit IVs recommended by \
+        //   XEP-0454, as some clients do not support 128-bit IVs)
+        let mut key = Zeroizing::new([0; KEY_LENGTH]);
+        let mut iv = vec![0; IV_LENGTH];
+
+        getrandom::fill(key.as_mut_slice()).ok()?;
+        getrandom::fill(&mut iv).ok()?;
+
+        Some(Self { key, iv })
+    }
+
+    pub fn is_encrypted_url(url: &str) -> bool {
+        url.get(..ENCRYPTED_URL_SCHEME.len())
+            .map(|scheme| scheme.eq_ignore_ascii_case(ENCRYPTED_URL_SCHEME))
+            .unwrap_or(false)
+    }
+
+    pub fn from_url(url: &str) -> Option<(String, Self)> {
+        // Parse an 'aesgcm://' URL, as per XEP-0454 (OMEMO Media Sharing); \
+        //   its fragment holds the IV (12 bytes, or 16 bytes for legacy \
+        //   clients) then the key (32 bytes), in hexadecimal
+        if !Self::is_encrypted_url(url) {
+            return None;
+        }
+
+        let (resource, fragment) = url[ENCRYPTED_URL_SCHEME.len()..].split_once('#')?;
+        let secret = Zeroizing::new(decode_hex(fragment)?);
+
+        let iv_length = match secret.len() {
+            44 => 12,
+            48 => 16,
+            _ => return None,
+        };
+
+        let mut key = Zeroizing::new([0; KEY_LENGTH]);
+
+        key.copy_from_slice(&secret[iv_length..]);
+
+        Some((
+            format!("{}{}", DECRYPTED_URL_SCHEME, resource),
+            Self {
+                key,
+                iv: secret[..iv_length].to_vec(),
+            },
+        ))
+    }
+
+    pub fn redact_url(url: &str) -> String {
+        // Remove URL fragment (which holds key material)
+        url.split('#').next().unwrap_or(url).to_string()
+    }
+
+    pub fn to_fragment(&self) -> String {
+        // Encode IV then key in hexadecimal (to be appended to an \
+        //   'aesgcm://' URL as its fragment)
+        self.iv
+            .iter()
+            .chain(self.key.iter())
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decryptor(&self) -> FileDecryptor {
+        let (cipher, authenticator) = self.initialize();
+
+        FileDecryptor {
+            encryption: self.clone(),
+            cipher,
+            authenticator,
+            held: Vec::with_capacity(TAG_LENGTH),
+        }
+    }
+
+    pub fn encryptor(&self) -> FileEncryptor {
+        let (cipher, authenticator) = self.initialize();
+
+        FileEncryptor {
+            cipher,
+            authenticator,
+        }
+    }
+
+    fn initialize(&self) -> (Ctr32BE<Aes256>, FileAuthenticator) {
+        let aes = Aes256::new(GenericArray::from_slice(self.key.as_slice()));
+
+        // Derive hash subkey
+        let mut hash_key = Block::default();
+
+        aes.encrypt_block(&mut hash_key);
+
+        let ghash = GHash::new(&hash_key);
+
+        // Derive pre-counter block from IV (96-bit IVs are used as-is, other \
+        //   IV lengths need to be hashed first, as per NIST SP 800-38D)
+        let mut counter = Block::default();
+
+        if self.iv.len() == 12 {
+            counter[..12].copy_from_slice(&self.iv);
+            counter[15] = 1;
+        } else {
+            let mut iv_ghash = ghash.clone();
+            let mut length_block = Block::default();
+
+            length_block[8..].copy_from_slice(&((self.iv.len() as u64) * 8).to_be_bytes());
+
+            iv_ghash.update_padded(&self.iv);
+            iv_ghash.update(&[length_block]);
+
+            counter = iv_ghash.finalize();
+        }
+
+        // Tag is masked with the encrypted pre-counter block
+        let mut tag_mask = counter;
+
+        aes.encrypt_block(&mut tag_mask);
+
+        // Payload is encrypted starting from the next counter block
+        let counter_value = u32::from_be_bytes(counter[12..].try_into().unwrap());
+
+        counter[12..].copy_from_slice(&counter_value.wrapping_add(1).to_be_bytes());
+
+        (
+            Ctr32BE::<Aes256>::new(GenericArray::from_slice(self.key.as_slice()), &counter),
+            FileAuthenticator {
+                ghash,
+                tag_mask,
+                pending: Vec::with_capacity(BLOCK_LENGTH),
+                length: 0,
+            },
+        )
+    }
+}
+
+impl FileAuthenticator {
+    fn update(&mut self, ciphertext: &[u8]) {
+        // Authenticate ciphertext (by full blocks only)
+        self.pending.extend_from_slice(ciphertext);
+        self.length += ciphertext.len() as u64;
+
+        let full_length = self.pending.len() - (self.pending.len() % BLOCK_LENGTH);
+
+        let blocks = self.pending[..full_length]
+            .chunks_exact(BLOCK_LENGTH)
+            .map(|block| *Block::from_slice(block))
+            .collect::<Vec<_>>();
+
+        self.ghash.update(&blocks);
+        self.pending.drain(..full_length);
+    }
+
+    fn tag(&self) -> Block {
+        let mut ghash = self.ghash.clone();
+        let mut length_block = Block::default();
+
+        length_block[8..].copy_from_slice(&(self.length * 8).to_be_bytes());
+
+        ghash.update_padded(&self.pending);
+        ghash.update(&[length_block]);
+
+        let mut tag = ghash.finalize();
+
+        tag.iter_mut()
+            .zip(self.tag_mask.iter())
+            .for_each(|(tag, mask)| *tag ^= mask);
+
+        tag
+    }
+}
+
+impl FileDecryptor {
+    pub fn reset(&mut self) {
+        *self = self.encryption.decryptor();
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Vec<u8> {
+        // The last bytes of the stream are the authentication tag, therefore \
+        //   always hold back the last bytes received, since any of them may \
+        //   be part of the tag
+        self.held.extend_from_slice(data);
+
+        if self.held.len() <= TAG_LENGTH {
+            return Vec::new();
+        }
+
+        let mut plaintext = self
+            .held
+            .drain(..(self.held.len() - TAG_LENGTH))
+            .collect::<Vec<_>>();
+
+        self.authenticator.update(&plaintext);
+
+        // Decrypt ciphertext
+        self.cipher.apply_keystream(&mut plaintext);
+
+        plaintext
+    }
+
+    pub fn finalize(&self) -> bool {
+        if self.held.len() != TAG_LENGTH {
+            return false;
+        }
+
+        // Compare tags in constant time
+        self.authenticator
+            .tag()
+            .iter()
+            .zip(self.held.iter())
+            .fold(0, |difference, (tag, held)| difference | (tag ^ held))
+            == 0
+    }
+}
+
+impl FileEncryptor {
+    pub fn update(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut ciphertext = data.to_vec();
+
+        self.cipher.apply_keystream(&mut ciphertext);
+        self.authenticator.update(&ciphertext);
+
+        ciphertext
+    }
+
+    pub fn finalize(&self) -> Vec<u8> {
+        // Authentication tag is appended to the ciphertext
+        self.authenticator.tag().to_vec()
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..(index + 2)], 16).ok())
+        .collect()
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::aead::consts::{U12, U16};
+    use aes_gcm::aead::Aead;
+    use aes_gcm::AesGcm;
+
+    const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn decrypt_chunked(encryption: &FileEncryption, data: &[u8], size: usize) -> (Vec<u8>, bool) {
+        let mut decryptor = encryption.decryptor();
+        let mut plaintext = Vec::new();
+
+        for chunk in data.chunks(size) {
+            plaintext.extend(decryptor.update(chunk));
+        }
+
+        (plaintext, decryptor.finalize())
+    }
+
+    fn make_plaintext() -> Vec<u8> {
+        (0..1000).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_parse_encrypted_url() {
+        let (url, encryption) = FileEncryption::from_url(&format!(
+            "aesgcm://upload.prose.org/files/image.png#{}{}",
+            "a0a1a2a3a4a5a6a7a8a9aaab", KEY_HEX
+        ))
+        .unwrap();
+
+        assert_eq!(url, "https://upload.prose.org/files/image.png");
+        assert_eq!(encryption.iv.len(), 12);
+        assert_eq!(encryption.key[31], 0x1f);
+
+        assert!(FileEncryption::from_url("https://prose.org/file#00").is_none());
+        assert!(FileEncryption::from_url("aesgcm://prose.org/file").is_none());
+        assert!(FileEncryption::from_url("aesgcm://prose.org/file#zz").is_none());
+        assert!(FileEncryption::is_encrypted_url("AESGCM://prose.org/file"));
+
+        assert_eq!(
+            FileEncryption::redact_url("aesgcm://prose.org/file#secret"),
+            "aesgcm://prose.org/file"
+        );
+        assert!(!format!("{:?}", encryption).contains("1f"));
+    }
+
+    #[test]
+    fn test_decrypt_96_bit_iv() {
+        let iv = [7; 12];
+        let key = decode_hex(KEY_HEX).unwrap();
+        let plaintext = make_plaintext();
+
+        let ciphertext = AesGcm::<Aes256, U12>::new_from_slice(&key)
+            .unwrap()
+            .encrypt(GenericArray::from_slice(&iv), plaintext.as_slice())
+            .unwrap();
+
+        let (_, encryption) = FileEncryption::from_url(&format!(
+            "aesgcm://prose.org/file#{}{}",
+            "07".repeat(12),
+            KEY_HEX
+        ))
+        .unwrap();
+
+        for size in [1, 15, 16, 17, 333, 4096] {
+            assert_eq!(
+                decrypt_chunked(&encryption, &ciphertext, size),
+                (plaintext.clone(), true)
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_128_bit_iv() {
+        let iv = [9; 16];
+        let key = decode_hex(KEY_HEX).unwrap();
+        let plaintext = make_plaintext();
+
+        let ciphertext = AesGcm::<Aes256, U16>::new_from_slice(&key)
+            .unwrap()
+            .encrypt(GenericArray::from_slice(&iv), plaintext.as_slice())
+            .unwrap();
+
+        let (_, encryption) = FileEncryption::from_url(&format!(
+            "aesgcm://prose.org/file#{}{}",
+            "09".repeat(16),
+            KEY_HEX
+        ))
+        .unwrap();
+
+        assert_eq!(
+            decrypt_chunked(&encryption, &ciphertext, 100),
+            (plaintext, true)
+        );
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let iv = [7; 12];
+        let key = decode_hex(KEY_HEX).unwrap();
+
+        let mut ciphertext = AesGcm::<Aes256, U12>::new_from_slice(&key)
+            .unwrap()
+            .encrypt(GenericArray::from_slice(&iv), make_plaintext().as_slice())
+            .unwrap();
+
+        let (_, encryption) = FileEncryption::from_url(&format!(
+            "aesgcm://prose.org/file#{}{}",
+            "07".repeat(12),
+            KEY_HEX
+        ))
+        .unwrap();
+
+        ciphertext[10] ^= 1;
+
+        assert!(!decrypt_chunked(&encryption, &ciphertext, 64).1);
+        assert!(!decrypt_chunked(&encryption, &ciphertext[..8], 64).1);
+    }
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let encryption = FileEncryption::generate().unwrap();
+        let plaintext = make_plaintext();
+
+        // Encrypt by chunks, then append tag
+        let mut encryptor = encryption.encryptor();
+        let mut ciphertext = Vec::new();
+
+        for chunk in plaintext.chunks(77) {
+            ciphertext.extend(encryptor.update(chunk));
+        }
+
+        ciphertext.extend(encryptor.finalize());
+
+        assert_eq!(ciphertext.len(), plaintext.len() + TAG_LENGTH);
+
+        // Decrypt with reference implementation
+        let fragment = decode_hex(&encryption.to_fragment()).unwrap();
+
+        let decrypted = AesGcm::<Aes256, U12>::new_from_slice(&fragment[12..])
+            .unwrap()
+            .decrypt(
+                GenericArray::from_slice(&fragment[..12]),
+                ciphertext.as_slice(),
+            )
+            .unwrap();
+
+        assert_eq!(decrypted, plaintext);
+
+        // Decrypt with streaming decryptor (from an URL)
+        let (_, parsed_encryption) = FileEncryption::from_url(&format!(
+            "aesgcm://prose.org/file#{}",
+            encryption.to_fragment()
+        ))
+        .unwrap();
+
+        assert_eq!(
+            decrypt_chunked(&parsed_encryption, &ciphertext, 500),
+            (plaintext, true)
+        );
+    }
+}
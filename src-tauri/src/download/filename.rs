@@ -0,0 +1,285 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use percent_encoding::percent_decode_str;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+// Extensions for common media types shared over XMPP (only types that map \
+//   to a single well-known extension are listed)
+const MIME_EXTENSIONS: [(&str, &str); 38] = [
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/avif", "avif"),
+    ("image/heic", "heic"),
+    ("image/heif", "heif"),
+    ("image/bmp", "bmp"),
+    ("image/tiff", "tiff"),
+    ("image/svg+xml", "svg"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+    ("video/quicktime", "mov"),
+    ("video/x-matroska", "mkv"),
+    ("video/mpeg", "mpeg"),
+    ("audio/mpeg", "mp3"),
+    ("audio/mp4", "m4a"),
+    ("audio/aac", "aac"),
+    ("audio/ogg", "ogg"),
+    ("audio/opus", "opus"),
+    ("audio/wav", "wav"),
+    ("audio/x-wav", "wav"),
+    ("audio/webm", "weba"),
+    ("audio/flac", "flac"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/json", "json"),
+    ("application/msword", "doc"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("text/html", "html"),
+    ("text/markdown", "md"),
+    ("text/calendar", "ics"),
+];
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn parse_quoted_string(characters: &mut Peekable<Chars>) -> String {
+    // Parse a quoted string (opening quote already consumed), un-escaping \
+    //   quoted pairs (eg. '\"')
+    let mut value = String::new();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => break,
+            '\\' => {
+                if let Some(escaped) = characters.next() {
+                    value.push(escaped);
+                }
+            }
+            _ => value.push(character),
+        }
+    }
+
+    value
+}
+
+fn parse_parameters(header: &str) -> Vec<(String, String)> {
+    // Parse parameters following the disposition type, as per RFC 6266 (eg. \
+    //   'attachment; filename="file.txt"'), values can either be tokens or \
+    //   quoted strings (which may contain separators)
+    let mut parameters = Vec::new();
+    let mut characters = header.chars().peekable();
+
+    // Skip disposition type
+    characters.by_ref().find(|character| *character == ';');
+
+    while characters.peek().is_some() {
+        let mut name = String::new();
+        let mut has_value = false;
+
+        for character in characters.by_ref() {
+            match character {
+                '=' => {
+                    has_value = true;
+
+                    break;
+                }
+                ';' => break,
+                _ => name.push(character),
+            }
+        }
+
+        // Ignore parameters without a value
+        if !has_value {
+            continue;
+        }
+
+        while characters
+            .next_if(|character| character.is_whitespace())
+            .is_some()
+        {}
+
+        let value = if characters.next_if_eq(&'"').is_some() {
+            let value = parse_quoted_string(&mut characters);
+
+            // Skip anything up to the next parameter
+            characters.by_ref().find(|character| *character == ';');
+
+            value
+        } else {
+            characters
+                .by_ref()
+                .take_while(|character| *character != ';')
+                .collect::<String>()
+                .trim()
+                .to_string()
+        };
+
+        parameters.push((name.trim().to_ascii_lowercase(), value));
+    }
+
+    parameters
+}
+
+fn decode_extended_value(value: &str) -> Option<String> {
+    // Decode an extended parameter value, as per RFC 5987 (eg. \
+    //   "UTF-8'en'na%C3%AFve.txt"), made of a charset, an optional language \
+    //   and percent-encoded bytes
+    let mut parts = value.splitn(3, '\'');
+
+    let (charset, _, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes = percent_decode_str(encoded).collect::<Vec<_>>();
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+pub fn content_disposition_filename(header: &str) -> Option<String> {
+    // The extended 'filename*' parameter takes precedence over 'filename', \
+    //   since it can carry non-ASCII characters (as per RFC 6266)
+    let parameters = parse_parameters(header);
+
+    let extended_filename = parameters
+        .iter()
+        .filter(|(name, _)| name == "filename*")
+        .find_map(|(_, value)| decode_extended_value(value));
+
+    let filename = parameters
+        .into_iter()
+        .find(|(name, _)| name == "filename")
+        .map(|(_, value)| value);
+
+    extended_filename
+        .or(filename)
+        .filter(|filename| !filename.trim().is_empty())
+}
+
+pub fn mime_extension(content_type: &str) -> Option<&'static str> {
+    // Ignore media type parameters (eg. '; charset=utf-8')
+    let mime_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or(content_type)
+        .trim();
+
+    MIME_EXTENSIONS
+        .iter()
+        .find(|(known_type, _)| known_type.eq_ignore_ascii_case(mime_type))
+        .map(|(_, extension)| *extension)
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_disposition_filename() {
+        assert_eq!(
+            content_disposition_filename("attachment; filename=report.pdf"),
+            Some("report.pdf".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("attachment; filename=\"my report.pdf\""),
+            Some("my report.pdf".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("inline;filename=\"a \\\"quoted\\\"; name.txt\";size=3"),
+            Some("a \"quoted\"; name.txt".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("ATTACHMENT; FileName = file.txt ; creation-date=x"),
+            Some("file.txt".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("attachment; foo; filename=file.txt"),
+            Some("file.txt".to_string())
+        );
+        assert_eq!(content_disposition_filename("attachment"), None);
+        assert_eq!(
+            content_disposition_filename("attachment; filename=\"\""),
+            None
+        );
+        assert_eq!(content_disposition_filename(""), None);
+    }
+
+    #[test]
+    fn test_content_disposition_filename_extended() {
+        assert_eq!(
+            content_disposition_filename(
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''na%C3%AFve%20%E2%82%AC.txt"
+            ),
+            Some("naïve €.txt".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename(
+                "attachment; filename*=utf-8'en'%E4%BD%A0%E5%A5%BD.pdf; filename=\"fallback.pdf\""
+            ),
+            Some("你好.pdf".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("attachment; filename*=iso-8859-1'de'%E4rger.txt"),
+            Some("ärger.txt".to_string())
+        );
+
+        // Invalid or unsupported extended values fall back to 'filename'
+        assert_eq!(
+            content_disposition_filename(
+                "attachment; filename*=UTF-8''%FF%FE.txt; filename=\"fallback.txt\""
+            ),
+            Some("fallback.txt".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename(
+                "attachment; filename*=KOI8-R''%F0.txt; filename=fallback.txt"
+            ),
+            Some("fallback.txt".to_string())
+        );
+        assert_eq!(
+            content_disposition_filename("attachment; filename*=no-quotes.txt"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mime_extension() {
+        assert_eq!(mime_extension("image/jpeg"), Some("jpg"));
+        assert_eq!(mime_extension("Image/PNG"), Some("png"));
+        assert_eq!(mime_extension("text/plain; charset=utf-8"), Some("txt"));
+        assert_eq!(mime_extension(" application/pdf "), Some("pdf"));
+        assert_eq!(mime_extension("application/octet-stream"), None);
+        assert_eq!(mime_extension(""), None);
+    }
+}
@@ -0,0 +1,426 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::sniff::FileKind;
+use super::store;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const HISTORY_FILE_NAME: &str = "downloads.json";
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DownloadStatus {
+    Queued,
+    Downloading,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+    Deferred,
+}
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DownloadRecord {
+    pub id: u64,
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub hashes: HashMap<String, String>,
+    #[serde(default)]
+    pub expected_size: Option<u64>,
+    pub path: Option<String>,
+    pub size: Option<u64>,
+    pub checksum: Option<String>,
+    pub completed_at: Option<u64>,
+    pub status: DownloadStatus,
+    #[serde(default)]
+    pub warning: Option<FileKind>,
+    #[serde(default)]
+    pub sender: Option<String>,
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub resumption: Option<DownloadResumption>,
+    #[serde(skip)]
+    pub canonical_path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadResumption {
+    pub path: String,
+    pub reserved: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub secret_headers: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct DownloadHistory {
+    path: Option<PathBuf>,
+    records: BTreeMap<u64, DownloadRecord>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl DownloadStatus {
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Self::Completed | Self::Failed | Self::Cancelled | Self::Deferred => true,
+            Self::Queued | Self::Downloading | Self::Paused => false,
+        }
+    }
+}
+
+impl DownloadRecord {
+    pub fn new(id: u64, url: &str, filename: &str) -> Self {
+        Self {
+            id,
+            url: url.to_string(),
+            filename: filename.to_string(),
+            hashes: HashMap::new(),
+            expected_size: None,
+            path: None,
+            size: None,
+            checksum: None,
+            completed_at: None,
+            status: DownloadStatus::Queued,
+            warning: None,
+            sender: None,
+            account: None,
+            resumption: None,
+            canonical_path: None,
+        }
+    }
+}
+
+impl DownloadHistory {
+    pub fn load(directory: Option<PathBuf>) -> Self {
+        let path = directory.map(|directory| directory.join(HISTORY_FILE_NAME));
+
+        // Read stored records (if any)
+        let records: Vec<DownloadRecord> =
+            path.as_deref().and_then(store::read).unwrap_or_default();
+
+        // Any download that was still in progress when the history was last \
+        //   stored got interrupted, therefore mark it as failed (it might \
+        //   get resumed later on, if it is resumable)
+        let records = records
+            .into_iter()
+            .map(|mut record| {
+                if !record.status.is_terminal() {
+                    record.status = DownloadStatus::Failed;
+                }
+
+                if record.status == DownloadStatus::Completed {
+                    record.canonical_path = record.path.as_deref().and_then(canonicalize);
+                }
+
+                (record.id, record)
+            })
+            .collect();
+
+        Self { path, records }
+    }
+
+    pub fn list(&self) -> Vec<DownloadRecord> {
+        self.records.values().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&DownloadRecord> {
+        self.records.get(&id)
+    }
+
+    pub fn find_completed(&self, canonical_path: &Path) -> Option<&DownloadRecord> {
+        // Match completed downloads by their canonical path, so that paths \
+        //   going through links or relative components match as well \
+        //   (the path must be canonicalized beforehand, as canonical paths \
+        //   of records are stored once they complete)
+        self.records.values().find(|record| {
+            record.status == DownloadStatus::Completed
+                && record.canonical_path.as_deref() == Some(canonical_path)
+        })
+    }
+
+    pub fn get_completed(&self, id: u64) -> Option<&DownloadRecord> {
+        self.records.get(&id).filter(|record| {
+            record.status == DownloadStatus::Completed && record.canonical_path.is_some()
+        })
+    }
+
+    pub fn take_interrupted(&mut self) -> Vec<(DownloadRecord, DownloadResumption)> {
+        // Take resumption details out of interrupted downloads, so that they \
+        //   only ever get restored once
+        self.records
+            .values_mut()
+            .filter(|record| record.status == DownloadStatus::Failed)
+            .filter_map(|record| {
+                record
+                    .resumption
+                    .take()
+                    .map(|resumption| (record.clone(), resumption))
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, record: DownloadRecord) {
+        self.records.insert(record.id, record);
+    }
+
+    pub fn update(&mut self, id: u64, update: impl FnOnce(&mut DownloadRecord)) {
+        if let Some(record) = self.records.get_mut(&id) {
+            update(record);
+        }
+    }
+
+    pub fn complete(
+        &mut self,
+        id: u64,
+        path: String,
+        canonical_path: Option<PathBuf>,
+        size: u64,
+        checksum: Option<String>,
+    ) {
+        let completed_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .ok();
+
+        self.update(id, |record| {
+            record.path = Some(path);
+            record.canonical_path = canonical_path;
+            record.size = Some(size);
+            record.checksum = checksum;
+            record.completed_at = completed_at;
+            record.status = DownloadStatus::Completed;
+        });
+    }
+
+    pub fn clear(&mut self) {
+        // Only forget about finished downloads, in-flight ones are kept
+        self.records
+            .retain(|_, record| !record.status.is_terminal());
+    }
+
+    pub fn snapshot(&self) -> Option<(PathBuf, Vec<u8>)> {
+        let path = self.path.clone()?;
+        let records = self.records.values().collect::<Vec<_>>();
+
+        serde_json::to_vec_pretty(&records)
+            .ok()
+            .map(|data| (path, data))
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn canonicalize(path: &str) -> Option<PathBuf> {
+    Path::new(path).canonicalize().ok()
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::make_directory;
+
+    #[test]
+    fn test_history_clear_keeps_in_flight() {
+        let mut history = DownloadHistory::default();
+
+        let mut completed = DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt");
+        let mut failed = DownloadRecord::new(2, "https://prose.org/b.txt", "b.txt");
+        let queued = DownloadRecord::new(3, "https://prose.org/c.txt", "c.txt");
+
+        completed.status = DownloadStatus::Completed;
+        failed.status = DownloadStatus::Failed;
+
+        history.insert(completed);
+        history.insert(failed);
+        history.insert(queued.clone());
+
+        history.clear();
+
+        assert_eq!(history.list(), vec![queued]);
+    }
+
+    #[test]
+    fn test_history_complete() {
+        let mut history = DownloadHistory::default();
+
+        history.insert(DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt"));
+        history.complete(
+            1,
+            "/tmp/a.txt".to_string(),
+            None,
+            42,
+            Some("abc".to_string()),
+        );
+
+        let record = history.get(1).unwrap();
+
+        assert_eq!(record.status, DownloadStatus::Completed);
+        assert_eq!(record.path.as_deref(), Some("/tmp/a.txt"));
+        assert_eq!(record.size, Some(42));
+        assert_eq!(record.checksum.as_deref(), Some("abc"));
+        assert!(record.completed_at.is_some());
+    }
+
+    #[test]
+    fn test_history_find_completed() {
+        let directory = make_directory();
+        std::fs::write(directory.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(directory.path().join("b.txt"), b"b").unwrap();
+
+        let mut history = DownloadHistory::default();
+
+        history.insert(DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt"));
+        history.insert(DownloadRecord::new(2, "https://prose.org/b.txt", "b.txt"));
+
+        let path_a = directory.path().join("a.txt").to_string_lossy().to_string();
+        let path_b = directory.path().join("b.txt").to_string_lossy().to_string();
+
+        history.complete(1, path_a.clone(), canonicalize(&path_a), 1, None);
+        history.update(2, |record| {
+            record.path = Some(path_b.clone());
+            record.canonical_path = canonicalize(&path_b);
+        });
+
+        // Only completed downloads are found, including via indirect paths
+        let find_completed = |path: &Path| {
+            history
+                .find_completed(&path.canonicalize().unwrap())
+                .map(|record| record.id)
+        };
+
+        assert_eq!(
+            find_completed(&directory.path().join(".").join("a.txt")),
+            Some(1)
+        );
+        assert_eq!(find_completed(&directory.path().join("b.txt")), None);
+
+        // Same goes when looking up by identifier
+        assert_eq!(history.get_completed(1).map(|record| record.id), Some(1));
+        assert!(history.get_completed(2).is_none());
+        assert!(history.get_completed(3).is_none());
+    }
+
+    #[test]
+    fn test_history_load_canonicalizes_completed() {
+        let directory = make_directory();
+        std::fs::write(directory.path().join("a.txt"), b"a").unwrap();
+
+        let mut completed = DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt");
+
+        completed.status = DownloadStatus::Completed;
+        completed.path = Some(
+            directory
+                .path()
+                .join(".")
+                .join("a.txt")
+                .to_string_lossy()
+                .to_string(),
+        );
+
+        std::fs::write(
+            directory.path().join(HISTORY_FILE_NAME),
+            serde_json::to_vec(&vec![&completed]).unwrap(),
+        )
+        .unwrap();
+
+        let history = DownloadHistory::load(Some(directory.path().to_path_buf()));
+
+        assert_eq!(
+            history.get(1).unwrap().canonical_path,
+            directory.path().join("a.txt").canonicalize().ok()
+        );
+    }
+
+    #[test]
+    fn test_history_load_marks_interrupted_as_failed() {
+        let directory = make_directory();
+
+        let mut downloading = DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt");
+        let mut completed = DownloadRecord::new(2, "https://prose.org/b.txt", "b.txt");
+
+        downloading.status = DownloadStatus::Downloading;
+        completed.status = DownloadStatus::Completed;
+
+        std::fs::write(
+            directory.path().join(HISTORY_FILE_NAME),
+            serde_json::to_vec(&vec![&downloading, &completed]).unwrap(),
+        )
+        .unwrap();
+
+        let history = DownloadHistory::load(Some(directory.path().to_path_buf()));
+
+        assert_eq!(history.get(1).unwrap().status, DownloadStatus::Failed);
+        assert_eq!(history.get(2).unwrap().status, DownloadStatus::Completed);
+    }
+
+    #[test]
+    fn test_history_take_interrupted() {
+        let directory = make_directory();
+
+        let resumption = DownloadResumption {
+            path: "/tmp/a.txt".to_string(),
+            reserved: true,
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            user_agent: None,
+            secret_headers: false,
+        };
+
+        let mut downloading = DownloadRecord::new(1, "https://prose.org/a.txt", "a.txt");
+        let queued = DownloadRecord::new(2, "https://prose.org/b.txt", "b.txt");
+
+        downloading.status = DownloadStatus::Downloading;
+        downloading.resumption = Some(resumption.clone());
+
+        std::fs::write(
+            directory.path().join(HISTORY_FILE_NAME),
+            serde_json::to_vec(&vec![&downloading, &queued]).unwrap(),
+        )
+        .unwrap();
+
+        let mut history = DownloadHistory::load(Some(directory.path().to_path_buf()));
+        let interrupted = history.take_interrupted();
+
+        // Only downloads that can be resumed are taken, and only once
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].0.id, 1);
+        assert_eq!(interrupted[0].1, resumption);
+        assert!(history.take_interrupted().is_empty());
+        assert_eq!(history.get(2).unwrap().status, DownloadStatus::Failed);
+    }
+}
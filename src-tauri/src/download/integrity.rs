@@ -0,0 +1,265 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Blake2b512};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+
+/**************************************************************************
+ * TYPES
+ * ************************************************************************* */
+
+type Blake2b256 = Blake2b<U32>;
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake2b256,
+    Blake2b512,
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake2b256(Blake2b256),
+    Blake2b512(Blake2b512),
+}
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadIntegrity {
+    hashes: Vec<(HashAlgorithm, Vec<u8>)>,
+    size: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct DownloadDigest {
+    integrity: DownloadIntegrity,
+    hashers: Vec<Hasher>,
+    length: u64,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl HashAlgorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        // Algorithm names as registered for XEP-0300 (Use of Cryptographic \
+        //   Hash Functions in XMPP)
+        match name {
+            "sha-256" => Some(Self::Sha256),
+            "sha-512" => Some(Self::Sha512),
+            "blake2b-256" => Some(Self::Blake2b256),
+            "blake2b-512" => Some(Self::Blake2b512),
+            _ => None,
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+            Self::Sha512 => Hasher::Sha512(Sha512::new()),
+            Self::Blake2b256 => Hasher::Blake2b256(Blake2b256::new()),
+            Self::Blake2b512 => Hasher::Blake2b512(Blake2b512::new()),
+        }
+    }
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha512(hasher) => hasher.update(data),
+            Self::Blake2b256(hasher) => hasher.update(data),
+            Self::Blake2b512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha512(hasher) => hasher.finalize().to_vec(),
+            Self::Blake2b256(hasher) => hasher.finalize().to_vec(),
+            Self::Blake2b512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+impl DownloadIntegrity {
+    pub fn new(hashes: &HashMap<String, String>, size: Option<u64>) -> Option<Self> {
+        // Acquire expected hashes (Base64-encoded as per XEP-0300); hashes \
+        //   using algorithms that are not supported are ignored, while \
+        //   invalid values for supported algorithms are rejected
+        let mut expected_hashes = Vec::new();
+
+        for (name, value) in hashes.iter() {
+            if let Some(algorithm) = HashAlgorithm::from_name(name) {
+                let value = BASE64.decode(value.trim()).ok()?;
+
+                expected_hashes.push((algorithm, value));
+            }
+        }
+
+        Some(Self {
+            hashes: expected_hashes,
+            size,
+        })
+    }
+
+    pub fn digest(&self) -> DownloadDigest {
+        DownloadDigest {
+            integrity: self.clone(),
+            hashers: self
+                .hashes
+                .iter()
+                .map(|(algorithm, _)| algorithm.hasher())
+                .collect(),
+            length: 0,
+        }
+    }
+}
+
+impl DownloadDigest {
+    pub fn reset(&mut self) {
+        *self = self.integrity.digest();
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for hasher in self.hashers.iter_mut() {
+            hasher.update(data);
+        }
+
+        self.length += data.len() as u64;
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn exceeds_size(&self) -> bool {
+        // Allows for downloads to be stopped early, as soon as more bytes \
+        //   than expected were received
+        self.integrity
+            .size
+            .map(|size| self.length > size)
+            .unwrap_or(false)
+    }
+
+    pub fn verify(self) -> bool {
+        if let Some(size) = self.integrity.size {
+            if self.length != size {
+                return false;
+            }
+        }
+
+        self.hashers
+            .into_iter()
+            .zip(self.integrity.hashes.iter())
+            .all(|(hasher, (_, expected))| hasher.finalize() == *expected)
+    }
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_integrity(hashes: &[(&str, &str)], size: Option<u64>) -> DownloadIntegrity {
+        DownloadIntegrity::new(
+            &hashes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            size,
+        )
+        .unwrap()
+    }
+
+    fn digest_of(integrity: &DownloadIntegrity, chunks: &[&[u8]]) -> DownloadDigest {
+        let mut digest = integrity.digest();
+
+        for chunk in chunks {
+            digest.update(chunk);
+        }
+
+        digest
+    }
+
+    #[test]
+    fn test_verify_known_hashes() {
+        let integrity = make_integrity(
+            &[
+                ("sha-256", "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="),
+                (
+                    "sha-512",
+                    "3a81oZNherrMQXNJriBBMRLm+k6JqX6iCp7u5ktV05ohkpkqJ0/BqDa6PCOj/uu9RU1EI2Q86A4qmslPpUyknw==",
+                ),
+                ("blake2b-256", "vd2BPGNCOXIxce8/7phXm5SWTjuxyz5CcmLIwGjVIxk="),
+                (
+                    "blake2b-512",
+                    "uoClP5gcTQ1qJ5e2nxL26UwhLxRoWsS3SxK7b9v/otF9h8U5Kqt5LcJS1d5FM8yVGNOKqNvxklq5I4bt1ACZIw==",
+                ),
+            ],
+            Some(3),
+        );
+
+        assert!(digest_of(&integrity, &[b"a", b"bc"]).verify());
+        assert!(!digest_of(&integrity, &[b"abd"]).verify());
+    }
+
+    #[test]
+    fn test_verify_size() {
+        let integrity = make_integrity(&[], Some(3));
+
+        assert!(digest_of(&integrity, &[b"abc"]).verify());
+        assert!(!digest_of(&integrity, &[b"ab"]).verify());
+        assert!(digest_of(&integrity, &[b"abcd"]).exceeds_size());
+        assert!(!digest_of(&integrity, &[b"abc"]).exceeds_size());
+    }
+
+    #[test]
+    fn test_reset_and_unsupported() {
+        let integrity = make_integrity(
+            &[
+                ("sha-256", "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="),
+                ("sha3-256", "not checked"),
+            ],
+            None,
+        );
+
+        let mut digest = digest_of(&integrity, &[b"garbage"]);
+
+        digest.reset();
+        digest.update(b"abc");
+
+        assert_eq!(digest.length(), 3);
+        assert!(digest.verify());
+
+        assert!(DownloadIntegrity::new(
+            &HashMap::from([("sha-256".to_string(), "%%%".to_string())]),
+            None
+        )
+        .is_none());
+    }
+}
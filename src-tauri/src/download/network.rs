@@ -0,0 +1,115 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use reqwest::cookie::Jar;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Client;
+use std::io;
+use std::sync::Arc;
+use thiserror::Error;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+#[cfg(target_os = "linux")]
+const NETWORK_MANAGER_DESTINATION: &str = "org.freedesktop.NetworkManager";
+#[cfg(target_os = "linux")]
+const NETWORK_MANAGER_PATH: &str = "/org/freedesktop/NetworkManager";
+
+// NetworkManager metered states that are considered metered ('yes' and \
+//   'guess-yes')
+// @ref: https://networkmanager.dev/docs/api/latest/nm-dbus-types.html\
+//   #NMMetered
+#[cfg(target_os = "linux")]
+const NETWORK_MANAGER_METERED_STATES: [u32; 2] = [1, 3];
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug, Error)]
+#[error("Could not resolve host")]
+pub struct ResolveError(#[source] pub io::Error);
+
+struct DownloadResolver;
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl Resolve for DownloadResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        // Resolve using the system resolver, although wrapping failures in a \
+        //   dedicated error, so that they can be told apart from other \
+        //   connection failures
+        Box::pin(async move {
+            let addresses = tokio::net::lookup_host((name.as_str(), 0))
+                .await
+                .map_err(ResolveError)?
+                .collect::<Vec<_>>();
+
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+pub fn build_client(cookies: bool) -> reqwest::Result<Client> {
+    // Use rustls, so that TLS failures can be told apart from other \
+    //   connection failures (as 'rustls::Error'); notice that certificates \
+    //   are verified against the platform roots, as with any other client
+    let mut builder = Client::builder()
+        .use_rustls_tls()
+        .dns_resolver(Arc::new(DownloadResolver));
+
+    if cookies {
+        builder = builder.cookie_provider(Arc::new(Jar::default()));
+    }
+
+    builder.build()
+}
+
+#[cfg(target_os = "linux")]
+pub async fn is_metered() -> bool {
+    // Ask NetworkManager whether the primary connection is metered (if \
+    //   NetworkManager is not available, assume the connection is not \
+    //   metered)
+    let metered: zbus::Result<u32> = async {
+        let connection = zbus::Connection::system().await?;
+
+        let proxy = zbus::Proxy::new(
+            &connection,
+            NETWORK_MANAGER_DESTINATION,
+            NETWORK_MANAGER_PATH,
+            NETWORK_MANAGER_DESTINATION,
+        )
+        .await?;
+
+        proxy.get_property("Metered").await
+    }
+    .await;
+
+    match metered {
+        Ok(state) => NETWORK_MANAGER_METERED_STATES.contains(&state),
+        Err(error) => {
+            log::debug!("Could not check if connection is metered: {}", error);
+
+            false
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn is_metered() -> bool {
+    // Metered connections are not reported on other platforms
+    false
+}
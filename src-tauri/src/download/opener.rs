@@ -0,0 +1,115 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use std::io;
+use std::path::Path;
+use tokio::process::Command;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+#[cfg(target_os = "linux")]
+const FILE_MANAGER_DESTINATION: &str = "org.freedesktop.FileManager1";
+#[cfg(target_os = "linux")]
+const FILE_MANAGER_PATH: &str = "/org/freedesktop/FileManager1";
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+#[cfg(target_os = "linux")]
+async fn linux_show_items(path: &Path) -> zbus::Result<()> {
+    // Ask the file manager to show the file in its folder, selecting it
+    // @ref: https://www.freedesktop.org/wiki/Specifications/file-manager-interface/
+    let uri = tauri::Url::from_file_path(path)
+        .map_err(|_| zbus::Error::Failure("Path is not absolute".to_string()))?;
+
+    let connection = zbus::Connection::session().await?;
+
+    connection
+        .call_method(
+            Some(FILE_MANAGER_DESTINATION),
+            FILE_MANAGER_PATH,
+            Some(FILE_MANAGER_DESTINATION),
+            "ShowItems",
+            &(vec![uri.as_str()], ""),
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn spawn_detached(command: &mut Command) -> Result<(), io::Error> {
+    // Spawn command, then wait for it in the background, so that its \
+    //   process gets reaped once it exits (and never lingers as a zombie)
+    let mut child = command.spawn()?;
+
+    tauri::async_runtime::spawn(async move {
+        if let Err(error) = child.wait().await {
+            log::debug!("Could not wait for spawned command: {}", error);
+        }
+    });
+
+    Ok(())
+}
+
+pub async fn open(path: &Path) -> Result<(), io::Error> {
+    // Open file with its default application
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+
+    #[cfg(target_os = "windows")]
+    let mut command = Command::new("explorer");
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = Command::new("xdg-open");
+
+    spawn_detached(command.arg(path))
+}
+
+pub async fn reveal(path: &Path) -> Result<(), io::Error> {
+    // Show file in the system file manager (selecting it whenever possible)
+    #[cfg(target_os = "linux")]
+    {
+        match linux_show_items(path).await {
+            Ok(_) => return Ok(()),
+            Err(error) => {
+                log::debug!("Could not show file in file manager: {}", error);
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+
+        command.arg("-R").arg(path);
+        command
+    };
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("explorer");
+
+        command.arg(format!("/select,{}", path.display()));
+        command
+    };
+
+    // Notice: 'xdg-open' cannot select files, therefore the parent folder \
+    //   gets opened instead.
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+
+        command.arg(path.parent().unwrap_or(path));
+        command
+    };
+
+    spawn_detached(&mut command)
+}
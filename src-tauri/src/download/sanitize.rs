@@ -0,0 +1,408 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use super::split_filename;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const FILENAME_FALLBACK: &str = "File";
+
+// Room left for suffixes appended to sanitized filenames later on (eg. \
+//   ' (12)' to make them unique, or '.part' for partial files)
+const FILENAME_SUFFIX_RESERVE: usize = 16;
+
+// Extensions longer than this are not worth preserving when truncating (they \
+//   are unlikely to be actual extensions)
+const FILENAME_EXTENSION_MAXIMUM: usize = 16;
+
+// Characters that cannot be used in filenames on Windows (path separators \
+//   are also reserved, but are handled separately)
+const FILENAME_CHARACTERS_RESERVED: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+// Device names that cannot be used as filenames on Windows (with or \
+//   without an extension)
+const FILENAME_NAMES_RESERVED: [&str; 32] = [
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4",
+    "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilenameLimit {
+    bytes: usize,
+    units: usize,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl FilenameLimit {
+    // Linux filesystems (ext4, btrfs, etc.) limit filenames to 255 bytes
+    #[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+    pub const LINUX: Self = Self {
+        bytes: 255,
+        units: usize::MAX,
+    };
+
+    // APFS limits filenames to 255 UTF-8 bytes, while HFS+ limits them to \
+    //   255 UTF-16 code units
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub const MACOS: Self = Self {
+        bytes: 255,
+        units: 255,
+    };
+
+    // NTFS (and exFAT) limit filenames to 255 UTF-16 code units
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub const WINDOWS: Self = Self {
+        bytes: usize::MAX,
+        units: 255,
+    };
+
+    #[cfg(target_os = "macos")]
+    pub const CURRENT: Self = Self::MACOS;
+
+    #[cfg(target_os = "windows")]
+    pub const CURRENT: Self = Self::WINDOWS;
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    pub const CURRENT: Self = Self::LINUX;
+
+    fn reserve(&self, length: usize) -> Self {
+        Self {
+            bytes: self.bytes.saturating_sub(length),
+            units: self.units.saturating_sub(length),
+        }
+    }
+
+    fn fits(&self, filename: &str) -> bool {
+        filename.len() <= self.bytes && filename.encode_utf16().count() <= self.units
+    }
+
+    fn truncate(&self, filename: &str) -> String {
+        if self.fits(filename) {
+            return filename.to_string();
+        }
+
+        // Preserve the extension (if it looks like one), and truncate the \
+        //   rest of the filename so that it fits along with the extension
+        let (pure_filename, extension) = match split_filename(filename) {
+            (pure_filename, extension)
+                if extension.chars().count() <= FILENAME_EXTENSION_MAXIMUM =>
+            {
+                (pure_filename, extension)
+            }
+            _ => (filename.to_string(), "".to_string()),
+        };
+
+        let (mut bytes, mut units) = (extension.len(), extension.encode_utf16().count());
+
+        let pure_filename = pure_filename
+            .chars()
+            .take_while(|character| {
+                bytes += character.len_utf8();
+                units += character.len_utf16();
+
+                bytes <= self.bytes && units <= self.units
+            })
+            .collect::<String>();
+
+        trim_filename(&format!("{}{}", trim_filename(&pure_filename), extension)).to_string()
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn is_invisible_character(character: char) -> bool {
+    // Control characters (C0 and C1 sets), as well as invisible formatting \
+    //   characters that can be used to spoof filenames (eg. a right-to-left \
+    //   override to make 'txt.exe' appear as 'exe.txt')
+    character.is_control()
+        || matches!(
+            character,
+            '\u{061C}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{2028}'..='\u{202E}'
+                | '\u{2060}'..='\u{2069}'
+                | '\u{FEFF}'
+        )
+}
+
+fn trim_filename(filename: &str) -> &str {
+    // Leading dots would create hidden files, while trailing dots and spaces \
+    //   are silently dropped on Windows
+    filename.trim_matches(|character: char| character == '.' || character.is_whitespace())
+}
+
+fn is_reserved_name(filename: &str) -> bool {
+    // Reserved names are reserved regardless of case, extension and trailing \
+    //   spaces (eg. 'con .txt' refers to the console)
+    let device_name = filename.split('.').next().unwrap_or(filename).trim_end();
+
+    FILENAME_NAMES_RESERVED
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(device_name))
+}
+
+pub fn sanitize_filename_with_limit(filename: &str, limit: FilenameLimit) -> String {
+    // Security: filenames are provided by remote parties, therefore they \
+    //   must never be able to point outside of the download directory, nor \
+    //   be misleading to the user. Rules are applied in order:
+    //   1. Remove control and invisible formatting characters
+    //   2. Only keep the last path component (ignoring '.' and '..')
+    //   3. Replace characters reserved on Windows with '_'
+    //   4. Trim leading dots, trailing dots and surrounding whitespace
+    //   5. Truncate to fit filesystem limits, preserving the extension
+    //   6. Prefix reserved Windows device names with '_'
+    //   7. Use a fallback filename if nothing is left
+    let visible_filename = filename
+        .chars()
+        .filter(|character| !is_invisible_character(*character))
+        .collect::<String>();
+
+    let component = visible_filename
+        .split(['/', '\\'])
+        .map(|component| {
+            component
+                .chars()
+                .map(|character| {
+                    if FILENAME_CHARACTERS_RESERVED.contains(&character) {
+                        '_'
+                    } else {
+                        character
+                    }
+                })
+                .collect::<String>()
+        })
+        .rfind(|component| !trim_filename(component).is_empty())
+        .unwrap_or_default();
+
+    let limit = limit.reserve(FILENAME_SUFFIX_RESERVE);
+    let mut sanitized_filename = limit.truncate(trim_filename(&component));
+
+    // Notice: the prefixed filename might need to be truncated again, which \
+    //   cannot make it reserved again as it now starts with '_'
+    if is_reserved_name(&sanitized_filename) {
+        sanitized_filename = limit.truncate(&format!("_{}", sanitized_filename));
+    }
+
+    if sanitized_filename.is_empty() {
+        FILENAME_FALLBACK.to_string()
+    } else {
+        sanitized_filename
+    }
+}
+
+pub fn sanitize_filename(filename: &str) -> String {
+    sanitize_filename_with_limit(filename, FilenameLimit::CURRENT)
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::path::{Component, Path};
+
+    const LIMITS: [FilenameLimit; 3] = [
+        FilenameLimit::LINUX,
+        FilenameLimit::MACOS,
+        FilenameLimit::WINDOWS,
+    ];
+
+    fn any_filename() -> impl Strategy<Value = String> {
+        // Mix of arbitrary characters and characters known to be dangerous
+        prop::collection::vec(
+            prop_oneof![
+                any::<char>().prop_map(|character| character.to_string()),
+                prop::sample::select(vec![
+                    "/", "\\", ".", "..", " ", ":", "?", "*", "\u{202E}", "\u{200B}", "\u{0}",
+                    "\n", "CON", "nul", "COM¹", "~", "C:", "你好", "🤰🏽", "\u{FEFF}",
+                ])
+                .prop_map(|value| value.to_string()),
+            ],
+            0..400,
+        )
+        .prop_map(|parts| parts.concat())
+    }
+
+    #[test]
+    fn test_sanitize_path_traversal() {
+        assert_eq!(sanitize_filename("file"), "file");
+        assert_eq!(sanitize_filename("file.txt"), "file.txt");
+        assert_eq!(sanitize_filename("file.tar.gz"), "file.tar.gz");
+        assert_eq!(sanitize_filename("file.tar.gz/"), "file.tar.gz");
+        assert_eq!(sanitize_filename("file.tar.gz\\\\"), "file.tar.gz");
+        assert_eq!(sanitize_filename("file.tar.gz...."), "file.tar.gz");
+        assert_eq!(sanitize_filename("file.tar.gz..\\.."), "file.tar.gz");
+        assert_eq!(sanitize_filename("C:\\file.tar.gz..//.."), "file.tar.gz");
+        assert_eq!(sanitize_filename("~/file.tar.gz"), "file.tar.gz");
+        assert_eq!(sanitize_filename("../../../../file.tar.gz"), "file.tar.gz");
+        assert_eq!(sanitize_filename("/./."), "File");
+        assert_eq!(sanitize_filename("/.../..."), "File");
+        assert_eq!(sanitize_filename(""), "File");
+    }
+
+    #[test]
+    fn test_sanitize_legal_characters() {
+        assert_eq!(sanitize_filename("me@prose.org.txt"), "me@prose.org.txt");
+        assert_eq!(sanitize_filename("[draft] notes.md"), "[draft] notes.md");
+        assert_eq!(sanitize_filename("~file~"), "~file~");
+        assert_eq!(sanitize_filename("🤰🏽¨¬ø¡你好"), "🤰🏽¨¬ø¡你好");
+    }
+
+    #[test]
+    fn test_sanitize_reserved() {
+        assert_eq!(sanitize_filename("what?.txt"), "what_.txt");
+        assert_eq!(sanitize_filename("a<b>c:d|e*f\".txt"), "a_b_c_d_e_f_.txt");
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_filename("Com1 .tar.gz"), "_Com1 .tar.gz");
+        assert_eq!(sanitize_filename("lpt¹"), "_lpt¹");
+        assert_eq!(sanitize_filename("console.txt"), "console.txt");
+        assert_eq!(sanitize_filename("COM10"), "COM10");
+    }
+
+    #[test]
+    fn test_sanitize_invisible() {
+        assert_eq!(sanitize_filename("\x00hi"), "hi");
+        assert_eq!(sanitize_filename("line\nbreak.txt"), "linebreak.txt");
+        assert_eq!(sanitize_filename("photo\u{202E}gpj.exe"), "photogpj.exe");
+        assert_eq!(sanitize_filename("zero\u{200B}width"), "zerowidth");
+        assert_eq!(sanitize_filename("\u{FEFF}bom.txt"), "bom.txt");
+    }
+
+    #[test]
+    fn test_sanitize_trim() {
+        assert_eq!(sanitize_filename(".bashrc"), "bashrc");
+        assert_eq!(sanitize_filename("..hidden.txt"), "hidden.txt");
+        assert_eq!(sanitize_filename("  spaced.txt  "), "spaced.txt");
+        assert_eq!(sanitize_filename("dots..."), "dots");
+        assert_eq!(sanitize_filename("file. . ."), "file");
+    }
+
+    #[test]
+    fn test_sanitize_length() {
+        let long_filename = format!("{}.tar.gz", "a".repeat(1000));
+
+        for limit in LIMITS {
+            let sanitized = sanitize_filename_with_limit(&long_filename, limit);
+
+            assert!(sanitized.ends_with("aaa.tar.gz"));
+            assert!(limit.reserve(FILENAME_SUFFIX_RESERVE).fits(&sanitized));
+        }
+
+        // Multi-byte characters count differently depending on filesystems
+        let wide_filename = format!("{}.txt", "你".repeat(200));
+
+        assert_eq!(
+            sanitize_filename_with_limit(&wide_filename, FilenameLimit::LINUX),
+            format!("{}.txt", "你".repeat(78))
+        );
+        assert_eq!(
+            sanitize_filename_with_limit(&wide_filename, FilenameLimit::WINDOWS),
+            format!("{}.txt", "你".repeat(200))
+        );
+
+        // Overlong extensions are not preserved
+        let long_extension = format!("file.{}", "x".repeat(1000));
+
+        assert_eq!(
+            sanitize_filename_with_limit(&long_extension, FilenameLimit::LINUX).len(),
+            255 - FILENAME_SUFFIX_RESERVE
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        #[test]
+        fn test_property_single_component(filename in any_filename()) {
+            let sanitized = sanitize_filename(&filename);
+            let path = Path::new("downloads").join(&sanitized);
+
+            prop_assert!(!sanitized.contains(['/', '\\']));
+            prop_assert_eq!(path.parent(), Some(Path::new("downloads")));
+            prop_assert!(matches!(path.components().next_back(), Some(Component::Normal(_))));
+        }
+
+        #[test]
+        fn test_property_no_forbidden_characters(filename in any_filename()) {
+            let sanitized = sanitize_filename(&filename);
+
+            prop_assert!(!sanitized.is_empty());
+            prop_assert!(!sanitized.chars().any(is_invisible_character));
+            prop_assert!(!sanitized.contains(FILENAME_CHARACTERS_RESERVED));
+            prop_assert!(!is_reserved_name(&sanitized));
+        }
+
+        #[test]
+        fn test_property_trimmed(filename in any_filename()) {
+            let sanitized = sanitize_filename(&filename);
+
+            prop_assert!(!sanitized.starts_with('.'));
+            prop_assert!(!sanitized.ends_with('.'));
+            prop_assert_eq!(sanitized.trim(), sanitized.as_str());
+        }
+
+        #[test]
+        fn test_property_fits_limits(filename in any_filename()) {
+            for limit in LIMITS {
+                let sanitized = sanitize_filename_with_limit(&filename, limit);
+
+                prop_assert!(limit.reserve(FILENAME_SUFFIX_RESERVE).fits(&sanitized));
+            }
+        }
+
+        #[test]
+        fn test_property_idempotent(filename in any_filename()) {
+            for limit in LIMITS {
+                let sanitized = sanitize_filename_with_limit(&filename, limit);
+
+                prop_assert_eq!(
+                    sanitize_filename_with_limit(&sanitized, limit),
+                    sanitized
+                );
+            }
+        }
+
+        #[test]
+        fn test_property_preserves_extension(
+            name in "[a-zA-Z0-9 _-]{0,600}[a-zA-Z0-9]",
+            extension in "[a-z0-9]{1,8}",
+        ) {
+            let suffix = format!(".{}", extension);
+
+            for limit in LIMITS {
+                let sanitized =
+                    sanitize_filename_with_limit(&format!("{}{}", name, suffix), limit);
+
+                prop_assert!(sanitized.ends_with(&suffix));
+            }
+        }
+
+        #[test]
+        fn test_property_safe_unchanged(filename in "[a-zA-Z0-9_-][a-zA-Z0-9 _@()\\[\\]-]{0,60}[a-zA-Z0-9_-]\\.[a-z]{1,4}") {
+            prop_assume!(!is_reserved_name(&filename));
+
+            prop_assert_eq!(sanitize_filename(&filename), filename);
+        }
+    }
+}
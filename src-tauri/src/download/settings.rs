@@ -0,0 +1,63 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::store;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const SETTINGS_FILE_NAME: &str = "download-settings.json";
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadSettingsValues {
+    pub directory: Option<PathBuf>,
+    #[serde(default)]
+    pub maximum_size: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: Option<u64>,
+    #[serde(default)]
+    pub metered_threshold: Option<u64>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct DownloadSettings {
+    path: Option<PathBuf>,
+    pub values: DownloadSettingsValues,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl DownloadSettings {
+    pub fn load(directory: Option<PathBuf>) -> Self {
+        let path = directory.map(|directory| directory.join(SETTINGS_FILE_NAME));
+
+        let values = path.as_deref().and_then(store::read).unwrap_or_default();
+
+        Self { path, values }
+    }
+
+    pub fn snapshot(&self) -> Option<(PathBuf, Vec<u8>)> {
+        let path = self.path.clone()?;
+
+        serde_json::to_vec_pretty(&self.values)
+            .ok()
+            .map(|data| (path, data))
+    }
+}
@@ -0,0 +1,247 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+// Amount of bytes read from the start of files to sniff their type
+pub const SNIFF_LENGTH: u64 = 512;
+
+const EXTENSIONS_SCRIPT: [&str; 11] = [
+    "sh", "bash", "zsh", "csh", "ksh", "fish", "run", "command", "py", "pl", "rb",
+];
+
+// Notice: this includes files run by the Windows shell when opened, eg. \
+//   shortcuts and script host files (as files are opened through it)
+const EXTENSIONS_WINDOWS_EXECUTABLE: [&str; 22] = [
+    "exe", "msi", "msp", "bat", "cmd", "com", "scr", "pif", "cpl", "ps1", "vbs", "vbe", "js",
+    "jse", "wsf", "wsh", "hta", "lnk", "url", "scf", "reg", "jar",
+];
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileKind {
+    Elf,
+    AppImage,
+    MachO,
+    WindowsExecutable,
+    Script,
+    DesktopEntry,
+    Pdf,
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Zip,
+    Gzip,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl FileKind {
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        // Identify file type from its magic bytes (checking the most specific \
+        //   signatures first, eg. AppImages also are ELF binaries)
+        if head.starts_with(b"\x7fELF") {
+            if head.get(8..10) == Some(&b"AI"[..]) {
+                return Some(Self::AppImage);
+            }
+
+            return Some(Self::Elf);
+        }
+
+        let kind = match head {
+            [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..]
+            | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..]
+            | [0xca, 0xfe, 0xba, 0xbe, ..] => Self::MachO,
+            [b'M', b'Z', ..] => Self::WindowsExecutable,
+            [b'#', b'!', ..] => Self::Script,
+            [b'%', b'P', b'D', b'F', b'-', ..] => Self::Pdf,
+            [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Self::Png,
+            [0xff, 0xd8, 0xff, ..] => Self::Jpeg,
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Self::Gif,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Self::Webp,
+            [b'P', b'K', 0x03, 0x04, ..] => Self::Zip,
+            [0x1f, 0x8b, ..] => Self::Gzip,
+            _ if is_desktop_entry(head) => Self::DesktopEntry,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+
+    fn from_extension(filename: &str) -> Option<Self> {
+        let extension = Path::new(filename)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+
+        match extension.as_str() {
+            "desktop" => Some(Self::DesktopEntry),
+            "appimage" => Some(Self::AppImage),
+            extension if EXTENSIONS_SCRIPT.contains(&extension) => Some(Self::Script),
+            extension if EXTENSIONS_WINDOWS_EXECUTABLE.contains(&extension) => {
+                Some(Self::WindowsExecutable)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_dangerous(&self) -> bool {
+        match self {
+            Self::Elf
+            | Self::AppImage
+            | Self::MachO
+            | Self::WindowsExecutable
+            | Self::Script
+            | Self::DesktopEntry => true,
+            Self::Pdf
+            | Self::Png
+            | Self::Jpeg
+            | Self::Gif
+            | Self::Webp
+            | Self::Zip
+            | Self::Gzip => false,
+        }
+    }
+
+    pub fn dangerous(head: &[u8], filename: &str) -> Option<Self> {
+        // Files are deemed dangerous if either their content or their \
+        //   extension says so (as both can be used to run them, depending on \
+        //   how they get opened)
+        Self::sniff(head)
+            .filter(Self::is_dangerous)
+            .or_else(|| Self::from_extension(filename))
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn is_desktop_entry(head: &[u8]) -> bool {
+    // Desktop entries start with their main group header, which can be \
+    //   preceded by blank lines and comments
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+
+    String::from_utf8_lossy(head)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line == "[Desktop Entry]")
+        .unwrap_or(false)
+}
+
+pub async fn read_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::new();
+
+    if let Ok(file) = File::open(path).await {
+        file.take(SNIFF_LENGTH).read_to_end(&mut head).await.ok();
+    }
+
+    head
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            FileKind::sniff(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00"),
+            Some(FileKind::Elf)
+        );
+        assert_eq!(
+            FileKind::sniff(b"\x7fELF\x02\x01\x01\x00AI\x02\x00"),
+            Some(FileKind::AppImage)
+        );
+        assert_eq!(
+            FileKind::sniff(b"\xcf\xfa\xed\xfe\x07\x00"),
+            Some(FileKind::MachO)
+        );
+        assert_eq!(
+            FileKind::sniff(b"MZ\x90\x00"),
+            Some(FileKind::WindowsExecutable)
+        );
+        assert_eq!(
+            FileKind::sniff(b"#!/bin/sh\nrm -rf ~"),
+            Some(FileKind::Script)
+        );
+        assert_eq!(
+            FileKind::sniff(b"\xef\xbb\xbf# Comment\n\n[Desktop Entry]\nExec=sh"),
+            Some(FileKind::DesktopEntry)
+        );
+        assert_eq!(FileKind::sniff(b"%PDF-1.7"), Some(FileKind::Pdf));
+        assert_eq!(
+            FileKind::sniff(b"\x89PNG\r\n\x1a\n\x00"),
+            Some(FileKind::Png)
+        );
+        assert_eq!(FileKind::sniff(b"\xff\xd8\xff\xe0"), Some(FileKind::Jpeg));
+        assert_eq!(FileKind::sniff(b"GIF89a"), Some(FileKind::Gif));
+        assert_eq!(
+            FileKind::sniff(b"RIFF\x00\x00\x00\x00WEBPVP8"),
+            Some(FileKind::Webp)
+        );
+        assert_eq!(FileKind::sniff(b"PK\x03\x04"), Some(FileKind::Zip));
+        assert_eq!(FileKind::sniff(b"\x1f\x8b\x08"), Some(FileKind::Gzip));
+        assert_eq!(FileKind::sniff(b"Hello world"), None);
+        assert_eq!(FileKind::sniff(b"# Title\n[Desktop Entry"), None);
+        assert_eq!(FileKind::sniff(b""), None);
+    }
+
+    #[test]
+    fn test_dangerous() {
+        assert_eq!(
+            FileKind::dangerous(b"\x7fELF\x02", "photo.jpg"),
+            Some(FileKind::Elf)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"Hello world", "install.SH"),
+            Some(FileKind::Script)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"", "app.desktop"),
+            Some(FileKind::DesktopEntry)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"", "Tool-x86_64.AppImage"),
+            Some(FileKind::AppImage)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"%PDF-1.7", "setup.exe"),
+            Some(FileKind::WindowsExecutable)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"L\x00\x00\x00", "report.pdf.lnk"),
+            Some(FileKind::WindowsExecutable)
+        );
+        assert_eq!(
+            FileKind::dangerous(b"<html>", "invoice.HTA"),
+            Some(FileKind::WindowsExecutable)
+        );
+        assert_eq!(FileKind::dangerous(b"%PDF-1.7", "report.pdf"), None);
+        assert_eq!(FileKind::dangerous(b"Hello world", "notes"), None);
+    }
+}
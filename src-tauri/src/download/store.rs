@@ -0,0 +1,46 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use log::warn;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+pub fn read<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    // Read stored data (if any, ignoring corrupted data)
+    let data = std::fs::read(path).ok()?;
+
+    serde_json::from_slice(&data)
+        .inspect_err(|err| warn!("Could not parse stored data at {:?}: {}", path, err))
+        .ok()
+}
+
+pub async fn write((path, data): (PathBuf, Vec<u8>)) {
+    // Write to a temporary file first, then move it over the target file, \
+    //   so that stored data never gets truncated if the app quits mid-write
+    let mut temporary_path = path.as_os_str().to_owned();
+
+    temporary_path.push(".tmp");
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).await.ok();
+    }
+
+    let result = match fs::write(&temporary_path, data).await {
+        Ok(_) => fs::rename(&temporary_path, &path).await,
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        warn!("Could not write stored data at {:?}: {}", path, err);
+    }
+}
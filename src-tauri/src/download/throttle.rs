@@ -0,0 +1,147 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct DownloadThrottle {
+    bucket: Mutex<Option<TokenBucket>>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        // Start with a full bucket (ie. allow a burst of 1 second of data)
+        Self {
+            rate,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn consume(&mut self, amount: u64, now: Instant) -> Duration {
+        // Refill bucket with tokens accumulated since last refill (never \
+        //   holding more than 1 second worth of data)
+        let elapsed = now.saturating_duration_since(self.last_refill);
+
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.rate as f64);
+        self.last_refill = now;
+
+        // Consume tokens, possibly going into debt (chunks can be larger \
+        //   than the bucket itself); the debt is then waited for, which also \
+        //   makes downloads sharing this bucket wait their turn
+        self.tokens -= amount as f64;
+
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / self.rate as f64)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+impl DownloadThrottle {
+    pub fn new(rate: Option<u64>) -> Self {
+        let throttle = Self::default();
+
+        throttle.set_rate(rate);
+        throttle
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|bucket| bucket.rate)
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        // Passing no rate (or a zero rate) removes the limit
+        *self.bucket.lock().unwrap() = rate.filter(|rate| *rate > 0).map(TokenBucket::new);
+    }
+
+    pub fn consume(&self, amount: u64) -> Duration {
+        // Return how long to wait before receiving more data
+        self.bucket
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(|bucket| bucket.consume(amount, Instant::now()))
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(1000);
+        let start = bucket.last_refill;
+
+        // Initial burst is allowed, then consumption goes into debt
+        assert_eq!(bucket.consume(1000, start), Duration::ZERO);
+        assert_eq!(bucket.consume(500, start), Duration::from_millis(500));
+
+        // Debt is paid back over time
+        assert_eq!(
+            bucket.consume(500, start + Duration::from_millis(500)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            bucket.consume(0, start + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+
+        // Bucket never holds more than 1 second worth of data
+        assert_eq!(
+            bucket.consume(2000, start + Duration::from_secs(60)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_throttle_rate() {
+        let throttle = DownloadThrottle::new(None);
+
+        assert_eq!(throttle.rate(), None);
+        assert_eq!(throttle.consume(u64::MAX), Duration::ZERO);
+
+        throttle.set_rate(Some(0));
+
+        assert_eq!(throttle.rate(), None);
+
+        throttle.set_rate(Some(1024));
+
+        assert_eq!(throttle.rate(), Some(1024));
+        assert!(throttle.consume(2048) > Duration::ZERO);
+    }
+}
@@ -12,17 +12,19 @@
 mod connection;
 mod download;
 mod logger;
+mod media_cache;
 mod menu;
 mod notifications;
+#[cfg(test)]
+mod testing;
+mod upload;
 
 /**************************************************************************
  * IMPORTS
  * ************************************************************************* */
 
 use tauri::tray::TrayIconEvent;
-#[cfg(target_os = "macos")]
-use tauri::RunEvent;
-use tauri::{AppHandle, Emitter, Manager, WebviewWindow, WindowEvent};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, WebviewWindow, WindowEvent};
 use tauri_plugin_deep_link::DeepLinkExt;
 #[cfg(target_os = "macos")]
 use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
@@ -33,12 +35,12 @@ use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
 
 fn restore_window_target(window: &WebviewWindow) {
     // Show the window? (if hidden)
-    if window.is_visible().unwrap_or(false) == false {
+    if !window.is_visible().unwrap_or(false) {
         window.show().unwrap();
     }
 
     // Also un-minimize the window? (if minimized)
-    if window.is_minimized().unwrap_or(false) == false {
+    if !window.is_minimized().unwrap_or(false) {
         window.unminimize().unwrap();
     }
 
@@ -84,6 +86,8 @@ async fn main() {
     builder = builder
         .plugin(connection::provide())
         .plugin(download::provide())
+        .plugin(upload::provide())
+        .plugin(media_cache::provide())
         .plugin(notifications::provide())
         .plugin(logger::provide());
 
@@ -101,7 +105,7 @@ async fn main() {
                 // Notice: this is needed, to avoid the screen becoming \
                 //   all-black if the window is hidden whilst still in full \
                 //   screen.
-                if window.is_fullscreen().unwrap_or(false) == true {
+                if window.is_fullscreen().unwrap_or(false) {
                     window.set_fullscreen(false).unwrap();
                 } else {
                     // Hide the window
@@ -115,9 +119,10 @@ async fn main() {
             WindowEvent::Focused(focused) => window.emit("window:focus", focused).unwrap(),
             _ => {}
         })
-        .on_tray_icon_event(|app, event| match event {
-            TrayIconEvent::DoubleClick { .. } => restore_window(app),
-            _ => {}
+        .on_tray_icon_event(|app, event| {
+            if let TrayIconEvent::DoubleClick { .. } = event {
+                restore_window(app);
+            }
         })
         .on_menu_event(menu::handler);
 
@@ -160,9 +165,16 @@ async fn main() {
         .build(tauri::generate_context!())
         .expect("error while running tauri application");
 
-    app.run(|_app, event| match event {
+    app.run(|app, event| match event {
+        RunEvent::ExitRequested { code, api, .. } => {
+            // Gracefully close all XMPP sessions before exiting
+            // Notice: this is especially important so that servers do not \
+            //   see ungraceful disconnects, which would leave presence \
+            //   lingering for a while on the network.
+            connection::shutdown(app, &api, code)
+        }
         #[cfg(target_os = "macos")]
-        RunEvent::Reopen { .. } => restore_window(_app),
+        RunEvent::Reopen { .. } => restore_window(app),
         _ => {}
     });
 }
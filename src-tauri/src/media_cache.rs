@@ -0,0 +1,754 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * MODULES
+ * ************************************************************************* */
+
+pub mod exif;
+mod index;
+mod thumbnail;
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::http::{Request, Response};
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{Manager, Runtime, State};
+use thiserror::Error;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::download::encryption::FileEncryption;
+use crate::download::store;
+use index::{MediaCacheEntry, MediaCacheIndex};
+use thumbnail::ImageMetadata;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const MEDIA_CACHE_SCHEME: &str = "prose-media";
+const MEDIA_CACHE_DIRECTORY: &str = "media";
+
+const MEDIA_CACHE_QUOTA: u64 = 512 * 1024 * 1024;
+
+// Responses are held in memory, therefore larger files are served in \
+//   multiple ranges (media elements request the next ranges by themselves)
+const MEDIA_CACHE_RESPONSE_SIZE_MAXIMUM: u64 = 8 * 1024 * 1024;
+
+// Only media is ever served from the cache, other files are served as \
+//   opaque binary data
+const MEDIA_CACHE_CONTENT_TYPE_PREFIXES: [&str; 3] = ["image/", "video/", "audio/"];
+const MEDIA_CACHE_CONTENT_TYPE_FALLBACK: &str = "application/octet-stream";
+
+const THUMBNAIL_SIZE_MINIMUM: u32 = 16;
+const THUMBNAIL_SIZE_MAXIMUM: u32 = 2048;
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Error, PartialEq, Eq)]
+pub enum MediaCacheError {
+    #[error("Could not obtain cache directory")]
+    CouldNotObtainDirectory,
+    #[error("Could not write cached file")]
+    CouldNotWriteFile,
+    #[error("Could not download file")]
+    DownloadError,
+    #[error("Server responded with HTTP status {status}")]
+    HttpStatus { status: u16 },
+    #[error("Cached file integrity mismatch")]
+    IntegrityMismatch,
+    #[error("Invalid cached file encryption key")]
+    InvalidEncryptionKey,
+    #[error("Cached file decryption failed")]
+    DecryptionFailed,
+    #[error("Cached file is too large")]
+    TooLarge,
+    #[error("Could not read cached file")]
+    CouldNotReadFile,
+    #[error("Cached file is not a supported image")]
+    InvalidImage,
+    #[error("Invalid thumbnail size")]
+    InvalidSize,
+}
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MediaCacheUsage {
+    size: u64,
+    quota: u64,
+    count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MediaCacheThumbnail {
+    url: String,
+    #[serde(flatten)]
+    metadata: ImageMetadata,
+}
+
+pub struct MediaCacheState {
+    client: Client,
+    index: Mutex<MediaCacheIndex>,
+    index_write: AsyncMutex<()>,
+    fetches: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+    quota: u64,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl MediaCacheState {
+    fn new(cache_directory: Option<PathBuf>) -> Self {
+        let directory = cache_directory.map(|directory| directory.join(MEDIA_CACHE_DIRECTORY));
+
+        Self {
+            client: Client::new(),
+            index: Mutex::new(MediaCacheIndex::load(directory)),
+            index_write: AsyncMutex::new(()),
+            fetches: Mutex::new(HashMap::new()),
+            quota: MEDIA_CACHE_QUOTA,
+        }
+    }
+
+    async fn persist_index(&self) {
+        let _write_guard = self.index_write.lock().await;
+
+        let snapshot = self.index.lock().unwrap().snapshot();
+
+        if let Some(snapshot) = snapshot {
+            store::write(snapshot).await;
+        }
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn cache_key(url: &str, hash: Option<&str>) -> String {
+    // Files are addressed by their URL and expected hash (if any), so that \
+    //   a file replaced at the same URL with a different hash does not get \
+    //   served from cache; key material held in URLs never gets stored as-is
+    let mut hasher = Sha256::new();
+
+    hasher.update(url.as_bytes());
+    hasher.update([0]);
+    hasher.update(hash.unwrap_or_default().to_lowercase().as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn cache_url(key: &str) -> String {
+    // Custom schemes are served over HTTP on Windows and Android
+    // @ref: https://docs.rs/tauri/2/tauri/struct.Builder.html\
+    //   #method.register_asynchronous_uri_scheme_protocol
+    if cfg!(any(target_os = "windows", target_os = "android")) {
+        format!("http://{}.localhost/{}", MEDIA_CACHE_SCHEME, key)
+    } else {
+        format!("{}://localhost/{}", MEDIA_CACHE_SCHEME, key)
+    }
+}
+
+fn is_cache_key(key: &str) -> bool {
+    key.len() == 64
+        && key
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn media_content_type(content_type: Option<&str>) -> Option<String> {
+    let content_type = content_type?.split(';').next()?.trim().to_lowercase();
+
+    if MEDIA_CACHE_CONTENT_TYPE_PREFIXES
+        .iter()
+        .any(|prefix| content_type.starts_with(prefix))
+    {
+        Some(content_type)
+    } else {
+        None
+    }
+}
+
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    // Parse a single byte range (eg. 'bytes=0-499', 'bytes=500-' or \
+    //   'bytes=-500'), returning its inclusive bounds; multiple ranges are \
+    //   not supported, in which case the whole file gets served
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+    if size == 0 || start.contains(',') || end.contains(',') {
+        return None;
+    }
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+
+            (size.saturating_sub(suffix), size - 1)
+        }
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+
+    if start <= end && start < size {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+fn bound_range(range: Option<(u64, u64)>, size: u64) -> Option<(u64, u64)> {
+    // Serve at most a bounded amount of bytes at once, for open-ended \
+    //   ranges or files requested as a whole alike; the returned range is \
+    //   then served as partial content
+    let (start, end) = match range {
+        Some(range) => range,
+        None if size > MEDIA_CACHE_RESPONSE_SIZE_MAXIMUM => (0, size - 1),
+        None => return None,
+    };
+
+    Some((
+        start,
+        end.min(start + MEDIA_CACHE_RESPONSE_SIZE_MAXIMUM - 1),
+    ))
+}
+
+async fn download_entry(
+    client: &Client,
+    url: &str,
+    hash: Option<&str>,
+    path: &Path,
+    quota: u64,
+) -> Result<(u64, Option<String>), MediaCacheError> {
+    // Acquire encryption key (if any)
+    let (url, encryption) = if FileEncryption::is_encrypted_url(url) {
+        let (url, encryption) =
+            FileEncryption::from_url(url).ok_or(MediaCacheError::InvalidEncryptionKey)?;
+
+        (url, Some(encryption))
+    } else {
+        (url.to_string(), None)
+    };
+
+    let mut response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|_| MediaCacheError::DownloadError)?;
+
+    if !response.status().is_success() {
+        return Err(MediaCacheError::HttpStatus {
+            status: response.status().as_u16(),
+        });
+    }
+
+    // Refuse files that would not fit in the cache at all
+    if response.content_length().unwrap_or(0) > quota {
+        return Err(MediaCacheError::TooLarge);
+    }
+
+    let content_type = media_content_type(
+        response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+    );
+
+    let mut file = File::create(path)
+        .await
+        .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+
+    let mut decryptor = encryption.map(|encryption| encryption.decryptor());
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+
+    // Drain bytes from HTTP response to file
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|_| MediaCacheError::DownloadError)?
+    {
+        let data = match decryptor {
+            Some(ref mut decryptor) => Cow::Owned(decryptor.update(&chunk)),
+            None => Cow::Borrowed(&chunk[..]),
+        };
+
+        size += data.len() as u64;
+
+        if size > quota {
+            return Err(MediaCacheError::TooLarge);
+        }
+
+        hasher.update(&data);
+
+        file.write_all(&data)
+            .await
+            .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+    }
+
+    file.flush()
+        .await
+        .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+
+    // Verify decrypted file authenticity (if file is encrypted), then \
+    //   verify its integrity (if expected hash is known)
+    if let Some(ref decryptor) = decryptor {
+        if !decryptor.finalize() {
+            return Err(MediaCacheError::DecryptionFailed);
+        }
+    }
+
+    if let Some(hash) = hash {
+        if !format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(hash) {
+            return Err(MediaCacheError::IntegrityMismatch);
+        }
+    }
+
+    Ok((size, content_type))
+}
+
+async fn prepare_part(path: &Path, key: &str) -> Result<PathBuf, MediaCacheError> {
+    // Files are written to a temporary file first, then moved to their final \
+    //   location, so that a cached file is never served partially
+    let directory = path
+        .parent()
+        .ok_or(MediaCacheError::CouldNotObtainDirectory)?;
+
+    fs::create_dir_all(directory)
+        .await
+        .map_err(|_| MediaCacheError::CouldNotObtainDirectory)?;
+
+    Ok(directory.join(format!("{}.{}.part", key, uuid::Uuid::new_v4())))
+}
+
+async fn insert_entry(state: &MediaCacheState, entry: MediaCacheEntry) {
+    // Register cached file, then evict least recently used files so that \
+    //   the cache fits its quota
+    let evicted_paths = {
+        let mut index = state.index.lock().unwrap();
+        let key = entry.key.clone();
+
+        index.insert(entry);
+        index.evict(state.quota, &key)
+    };
+
+    for evicted_path in evicted_paths {
+        fs::remove_file(evicted_path).await.ok();
+    }
+
+    state.persist_index().await;
+}
+
+async fn cache(
+    state: &MediaCacheState,
+    url: &str,
+    hash: Option<&str>,
+) -> Result<String, MediaCacheError> {
+    let key = cache_key(url, hash);
+
+    // Merge concurrent fetches of the same file, so that it only gets \
+    //   downloaded once (other fetches wait for it, then get served from \
+    //   cache)
+    let fetch = state
+        .fetches
+        .lock()
+        .unwrap()
+        .entry(key.clone())
+        .or_default()
+        .clone();
+
+    let fetch_guard = fetch.lock().await;
+
+    let result = cache_entry(state, url, hash, key.clone()).await;
+
+    // Forget about this fetch, unless other fetches are waiting for it
+    {
+        let mut fetches = state.fetches.lock().unwrap();
+
+        if Arc::strong_count(&fetch) <= 2 {
+            fetches.remove(&key);
+        }
+    }
+
+    drop(fetch_guard);
+
+    result
+}
+
+async fn cache_entry(
+    state: &MediaCacheState,
+    url: &str,
+    hash: Option<&str>,
+    key: String,
+) -> Result<String, MediaCacheError> {
+    // Serve from cache? (if already cached)
+    let path = {
+        let mut index = state.index.lock().unwrap();
+
+        if index.touch(&key).is_some() {
+            return Ok(key);
+        }
+
+        index
+            .path(&key)
+            .ok_or(MediaCacheError::CouldNotObtainDirectory)?
+    };
+
+    // Download file, then move it to its final location
+    let part_path = prepare_part(&path, &key).await?;
+
+    let result = download_entry(&state.client, url, hash, &part_path, state.quota).await;
+
+    let (size, content_type) = match result {
+        Ok(result) => result,
+        Err(error) => {
+            fs::remove_file(&part_path).await.ok();
+
+            return Err(error);
+        }
+    };
+
+    fs::rename(&part_path, &path)
+        .await
+        .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+
+    insert_entry(
+        state,
+        MediaCacheEntry::new(&key, &FileEncryption::redact_url(url), size, content_type),
+    )
+    .await;
+
+    Ok(key)
+}
+
+async fn serve(state: &MediaCacheState, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let respond = |status: StatusCode| Response::builder().status(status).body(Vec::new()).unwrap();
+
+    // Acquire cached file (marking it as used)
+    let key = request.uri().path().trim_start_matches('/');
+
+    if !is_cache_key(key) {
+        return respond(StatusCode::NOT_FOUND);
+    }
+
+    let (entry, path) = {
+        let mut index = state.index.lock().unwrap();
+
+        match (index.touch(key), index.path(key)) {
+            (Some(entry), Some(path)) => (entry, path),
+            _ => return respond(StatusCode::NOT_FOUND),
+        }
+    };
+
+    let mut file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(_) => return respond(StatusCode::NOT_FOUND),
+    };
+
+    // Serve requested range (if any, eg. when seeking in videos)
+    let range = bound_range(
+        request
+            .headers()
+            .get(RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|range| parse_range(range, entry.size)),
+        entry.size,
+    );
+
+    let (start, end) = range.unwrap_or((0, entry.size.saturating_sub(1)));
+    let length = if entry.size > 0 { end - start + 1 } else { 0 };
+
+    let mut body = vec![0; length as usize];
+
+    let read = async {
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(&mut body).await
+    };
+
+    if read.await.is_err() {
+        return respond(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Notice: cached files are sandboxed, as they come from third parties \
+    //   and should never be able to run scripts with the application origin.
+    let mut response = Response::builder()
+        .header(
+            CONTENT_TYPE,
+            entry
+                .content_type
+                .as_deref()
+                .unwrap_or(MEDIA_CACHE_CONTENT_TYPE_FALLBACK),
+        )
+        .header(CONTENT_LENGTH, length)
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Security-Policy", "sandbox")
+        .header("X-Content-Type-Options", "nosniff");
+
+    if range.is_some() {
+        response = response.status(StatusCode::PARTIAL_CONTENT).header(
+            CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, entry.size),
+        );
+    }
+
+    response.body(body).unwrap()
+}
+
+/**************************************************************************
+ * COMMANDS
+ * ************************************************************************* */
+
+#[tauri::command]
+pub async fn fetch(
+    state: State<'_, MediaCacheState>,
+    url: &str,
+    hash: Option<String>,
+) -> Result<String, MediaCacheError> {
+    let key = cache(&state, url, hash.as_deref()).await?;
+
+    Ok(cache_url(&key))
+}
+
+#[tauri::command]
+pub async fn thumbnail(
+    state: State<'_, MediaCacheState>,
+    url: &str,
+    hash: Option<String>,
+    size: u32,
+) -> Result<MediaCacheThumbnail, MediaCacheError> {
+    if !(THUMBNAIL_SIZE_MINIMUM..=THUMBNAIL_SIZE_MAXIMUM).contains(&size) {
+        return Err(MediaCacheError::InvalidSize);
+    }
+
+    // Acquire source image (from cache, or download it first), then derive \
+    //   its thumbnail key from its own key and the requested size
+    let source_key = cache(&state, url, hash.as_deref()).await?;
+    let key = cache_key(&source_key, Some(&format!("thumbnail:{}", size)));
+
+    // Serve from cache? (if already generated)
+    let (source_path, path) = {
+        let mut index = state.index.lock().unwrap();
+
+        if let Some(metadata) = index.touch(&key).and_then(|entry| entry.metadata) {
+            return Ok(MediaCacheThumbnail {
+                url: cache_url(&key),
+                metadata,
+            });
+        }
+
+        (
+            index
+                .path(&source_key)
+                .ok_or(MediaCacheError::CouldNotObtainDirectory)?,
+            index
+                .path(&key)
+                .ok_or(MediaCacheError::CouldNotObtainDirectory)?,
+        )
+    };
+
+    let source = fs::read(&source_path)
+        .await
+        .map_err(|_| MediaCacheError::CouldNotReadFile)?;
+
+    // Generate thumbnail off the asynchronous runtime (as decoding and \
+    //   scaling images is computationally intensive)
+    let thumbnail =
+        tauri::async_runtime::spawn_blocking(move || thumbnail::generate(&source, size))
+            .await
+            .map_err(|_| MediaCacheError::InvalidImage)?
+            .map_err(|_| MediaCacheError::InvalidImage)?;
+
+    let part_path = prepare_part(&path, &key).await?;
+
+    if fs::write(&part_path, &thumbnail.data).await.is_err() {
+        fs::remove_file(&part_path).await.ok();
+
+        return Err(MediaCacheError::CouldNotWriteFile);
+    }
+
+    fs::rename(&part_path, &path)
+        .await
+        .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+
+    let mut entry = MediaCacheEntry::new(
+        &key,
+        &FileEncryption::redact_url(url),
+        thumbnail.data.len() as u64,
+        Some(thumbnail.content_type.to_string()),
+    );
+
+    entry.metadata = Some(thumbnail.metadata.clone());
+
+    insert_entry(&state, entry).await;
+
+    Ok(MediaCacheThumbnail {
+        url: cache_url(&key),
+        metadata: thumbnail.metadata,
+    })
+}
+
+#[tauri::command]
+pub fn usage(state: State<'_, MediaCacheState>) -> MediaCacheUsage {
+    let index = state.index.lock().unwrap();
+
+    MediaCacheUsage {
+        size: index.usage(),
+        quota: state.quota,
+        count: index.count(),
+    }
+}
+
+#[tauri::command]
+pub async fn clear(state: State<'_, MediaCacheState>) -> Result<(), MediaCacheError> {
+    let directory = {
+        let mut index = state.index.lock().unwrap();
+
+        index.clear();
+        index.directory().cloned()
+    };
+
+    // Remove all cached files (including any orphaned file)
+    if let Some(directory) = directory {
+        if directory.exists() {
+            fs::remove_dir_all(&directory)
+                .await
+                .map_err(|_| MediaCacheError::CouldNotWriteFile)?;
+        }
+    }
+
+    state.persist_index().await;
+
+    Ok(())
+}
+
+/**************************************************************************
+ * PROVIDERS
+ * ************************************************************************* */
+
+pub fn provide<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("media-cache")
+        .invoke_handler(tauri::generate_handler![fetch, thumbnail, usage, clear])
+        .register_asynchronous_uri_scheme_protocol(
+            MEDIA_CACHE_SCHEME,
+            |context, request, responder| {
+                let app_handle = context.app_handle().clone();
+
+                tauri::async_runtime::spawn(async move {
+                    let state = app_handle.state::<MediaCacheState>();
+
+                    responder.respond(serve(&state, &request).await);
+                });
+            },
+        )
+        .setup(|app_handle, _| {
+            app_handle.manage(MediaCacheState::new(app_handle.path().app_cache_dir().ok()));
+
+            Ok(())
+        })
+        .build()
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{make_directory, serve_http_with};
+
+    #[test]
+    fn test_cache_key() {
+        let key = cache_key("https://prose.org/image.png", None);
+
+        assert!(is_cache_key(&key));
+        assert_eq!(key, cache_key("https://prose.org/image.png", None));
+        assert_ne!(key, cache_key("https://prose.org/image.png", Some("ab")));
+        assert_eq!(
+            cache_key("https://prose.org/image.png", Some("AB")),
+            cache_key("https://prose.org/image.png", Some("ab"))
+        );
+
+        assert!(!is_cache_key("../index.json"));
+        assert!(!is_cache_key(&key.to_uppercase()));
+    }
+
+    #[test]
+    fn test_media_content_type() {
+        assert_eq!(
+            media_content_type(Some("image/PNG; charset=binary")),
+            Some("image/png".to_string())
+        );
+        assert_eq!(
+            media_content_type(Some("video/mp4")),
+            Some("video/mp4".to_string())
+        );
+        assert_eq!(media_content_type(Some("text/html")), None);
+        assert_eq!(media_content_type(None), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-499", 1000), Some((0, 499)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=-200", 1000), Some((800, 999)));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=500-100", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+    }
+
+    #[test]
+    fn test_bound_range() {
+        const MAXIMUM: u64 = MEDIA_CACHE_RESPONSE_SIZE_MAXIMUM;
+
+        assert_eq!(bound_range(None, 1000), None);
+        assert_eq!(bound_range(Some((0, 499)), 1000), Some((0, 499)));
+
+        // Files served as a whole and open-ended ranges get bounded
+        assert_eq!(bound_range(None, MAXIMUM * 3), Some((0, MAXIMUM - 1)));
+        assert_eq!(
+            bound_range(Some((MAXIMUM, MAXIMUM * 3 - 1)), MAXIMUM * 3),
+            Some((MAXIMUM, MAXIMUM * 2 - 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_merges_fetches() {
+        let directory = make_directory();
+        let (url, requests) = serve_http_with("200 OK", "", b"GIF89a").await;
+
+        let state = MediaCacheState::new(Some(directory.path().to_path_buf()));
+
+        let keys = futures::future::join_all((0..5).map(|_| cache(&state, &url, None))).await;
+
+        // All fetches are served from a single download
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert!(keys
+            .iter()
+            .all(|key| key.as_deref() == Ok(cache_key(&url, None).as_str())));
+        assert!(state.fetches.lock().unwrap().is_empty());
+    }
+}
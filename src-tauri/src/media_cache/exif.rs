@@ -0,0 +1,328 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+// Amount of bytes read from the start of images to strip their metadata \
+//   (JPEG EXIF segments are limited to 64 KiB and come first, while PNG \
+//   EXIF chunks come before image data)
+pub const HEAD_LENGTH: u64 = 1024 * 1024;
+
+const JPEG_MARKER_SOI: [u8; 2] = [0xff, 0xd8];
+const JPEG_MARKER_APP1: u8 = 0xe1;
+const JPEG_MARKER_SOS: u8 = 0xda;
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_CHUNK_EXIF: &[u8] = b"eXIf";
+const PNG_CHUNK_DATA: &[u8] = b"IDAT";
+
+const TIFF_TAG_GPS_IFD: u16 = 0x8825;
+const TIFF_ENTRY_LENGTH: usize = 12;
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            b"II\x2a\x00" => true,
+            b"MM\x00\x2a" => false,
+            _ => return None,
+        };
+
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<usize> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        } as usize)
+    }
+
+    fn clear(&mut self, offset: usize, length: usize) -> Option<()> {
+        self.data
+            .get_mut(offset..offset.checked_add(length)?)?
+            .fill(0);
+
+        Some(())
+    }
+
+    fn strip_location(&mut self) -> Option<bool> {
+        // Find GPS IFD (which is referenced from the first IFD)
+        let ifd = self.read_u32(4)?;
+        let count = self.read_u16(ifd)? as usize;
+
+        let gps_ifd = (0..count)
+            .map(|index| ifd + 2 + index * TIFF_ENTRY_LENGTH)
+            .find(|entry| self.read_u16(*entry) == Some(TIFF_TAG_GPS_IFD))
+            .and_then(|entry| self.read_u32(entry + 8));
+
+        let gps_ifd = match gps_ifd {
+            Some(gps_ifd) => gps_ifd,
+            None => return Some(false),
+        };
+
+        // Clear all GPS entries and their values (in place, so that offsets \
+        //   to other data remain valid), which leaves an empty GPS IFD
+        let gps_count = self.read_u16(gps_ifd)? as usize;
+
+        for index in 0..gps_count {
+            let entry = gps_ifd + 2 + index * TIFF_ENTRY_LENGTH;
+
+            let value_length = type_length(self.read_u16(entry + 2)?) * self.read_u32(entry + 4)?;
+
+            // Values larger than 4 bytes are stored out of the entry
+            if value_length > 4 {
+                let value_offset = self.read_u32(entry + 8)?;
+
+                self.clear(value_offset, value_length)?;
+            }
+
+            self.clear(entry, TIFF_ENTRY_LENGTH)?;
+        }
+
+        self.clear(gps_ifd, 2)?;
+
+        Some(gps_count > 0)
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn type_length(field_type: u16) -> usize {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+fn strip_jpeg_location(head: &mut [u8]) -> bool {
+    // Walk JPEG segments until image data starts, stripping location from \
+    //   EXIF segments
+    let mut offset = JPEG_MARKER_SOI.len();
+    let mut stripped = false;
+
+    while let Some(&[0xff, marker, length_high, length_low]) = head.get(offset..offset + 4) {
+        if marker == JPEG_MARKER_SOS {
+            break;
+        }
+
+        let length = u16::from_be_bytes([length_high, length_low]) as usize;
+        let segment = match head.get_mut(offset + 4..offset + 2 + length) {
+            Some(segment) => segment,
+            None => break,
+        };
+
+        if marker == JPEG_MARKER_APP1 && segment.starts_with(JPEG_EXIF_HEADER) {
+            if let Some(mut tiff) = Tiff::new(&mut segment[JPEG_EXIF_HEADER.len()..]) {
+                stripped |= tiff.strip_location().unwrap_or(false);
+            }
+        }
+
+        offset += 2 + length;
+    }
+
+    stripped
+}
+
+fn strip_png_location(head: &mut Vec<u8>) -> bool {
+    // Walk PNG chunks until image data starts, removing EXIF chunks \
+    //   altogether (as their checksum would not match once modified)
+    let mut offset = PNG_SIGNATURE.len();
+    let mut stripped = false;
+
+    while let Some(header) = head.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+
+        if chunk_type == PNG_CHUNK_DATA {
+            break;
+        }
+
+        // Chunk is made of its length, type, data then checksum
+        let chunk_length = 12 + length;
+
+        if offset + chunk_length > head.len() {
+            break;
+        }
+
+        if chunk_type == PNG_CHUNK_EXIF {
+            head.drain(offset..offset + chunk_length);
+
+            stripped = true;
+        } else {
+            offset += chunk_length;
+        }
+    }
+
+    stripped
+}
+
+pub fn strip_location(head: &mut Vec<u8>) -> bool {
+    // Strip location from image metadata (JPEG and PNG images only), \
+    //   returning whether any location was found
+    if head.starts_with(&JPEG_MARKER_SOI) {
+        strip_jpeg_location(head)
+    } else if head.starts_with(PNG_SIGNATURE) {
+        strip_png_location(head)
+    } else {
+        false
+    }
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tiff() -> Vec<u8> {
+        // Little-endian TIFF, with a first IFD holding an orientation entry \
+        //   and a GPS IFD pointer, then a GPS IFD holding a latitude \
+        //   reference (inline) and a latitude (3 rationals, out of entry)
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+
+        tiff.extend([2, 0]);
+        tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        tiff.extend([0x25, 0x88, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend([0, 0, 0, 0]);
+
+        tiff.extend([2, 0]);
+        tiff.extend([0x01, 0x00, 2, 0, 2, 0, 0, 0, b'N', 0, 0, 0]);
+        tiff.extend([0x02, 0x00, 5, 0, 3, 0, 0, 0, 68, 0, 0, 0]);
+        tiff.extend([0, 0, 0, 0]);
+
+        tiff.extend((1..=24).collect::<Vec<u8>>());
+
+        tiff
+    }
+
+    fn make_jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut jpeg = JPEG_MARKER_SOI.to_vec();
+
+        jpeg.extend([0xff, 0xe0, 0x00, 0x04, 0x00, 0x00]);
+        jpeg.extend([0xff, JPEG_MARKER_APP1]);
+        jpeg.extend(((2 + JPEG_EXIF_HEADER.len() + tiff.len()) as u16).to_be_bytes());
+        jpeg.extend(JPEG_EXIF_HEADER);
+        jpeg.extend(tiff);
+        jpeg.extend([0xff, JPEG_MARKER_SOS, 0x00, 0x02, 0xab, 0xcd]);
+
+        jpeg
+    }
+
+    #[test]
+    fn test_strip_jpeg_location() {
+        let tiff = make_tiff();
+        let mut jpeg = make_jpeg(&tiff);
+        let length = jpeg.len();
+
+        assert!(strip_location(&mut jpeg));
+        assert_eq!(jpeg.len(), length);
+
+        // Orientation is kept, while GPS IFD is emptied
+        let mut stripped = jpeg[12 + JPEG_EXIF_HEADER.len()..][..tiff.len()].to_vec();
+        let tiff_stripped = Tiff::new(&mut stripped).unwrap();
+
+        assert_eq!(tiff_stripped.read_u16(10), Some(0x0112));
+        assert_eq!(tiff_stripped.read_u16(38), Some(0));
+        assert!(stripped[40..].iter().all(|byte| *byte == 0));
+
+        // Image data is left untouched
+        assert!(jpeg.ends_with(&[0xff, JPEG_MARKER_SOS, 0x00, 0x02, 0xab, 0xcd]));
+
+        // Stripping again finds no location
+        assert!(!strip_location(&mut jpeg));
+    }
+
+    #[test]
+    fn test_strip_jpeg_location_big_endian() {
+        let mut tiff = b"MM\x00\x2a\x00\x00\x00\x08".to_vec();
+
+        tiff.extend([0, 1]);
+        tiff.extend([0x88, 0x25, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26]);
+        tiff.extend([0, 0, 0, 0]);
+        tiff.extend([0, 1]);
+        tiff.extend([0, 1, 0, 2, 0, 0, 0, 2, b'S', 0, 0, 0]);
+        tiff.extend([0, 0, 0, 0]);
+
+        let mut jpeg = make_jpeg(&tiff);
+
+        assert!(strip_location(&mut jpeg));
+        assert!(!jpeg.windows(2).any(|window| window == [b'S', 0]));
+    }
+
+    #[test]
+    fn test_strip_png_location() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        let tiff = make_tiff();
+
+        png.extend([0, 0, 0, 13]);
+        png.extend(b"IHDR");
+        png.extend([0; 13 + 4]);
+        png.extend((tiff.len() as u32).to_be_bytes());
+        png.extend(PNG_CHUNK_EXIF);
+        png.extend(&tiff);
+        png.extend([0; 4]);
+        png.extend([0, 0, 0, 1]);
+        png.extend(PNG_CHUNK_DATA);
+        png.extend([0; 1 + 4]);
+
+        let length = png.len();
+
+        assert!(strip_location(&mut png));
+        assert_eq!(png.len(), length - 12 - tiff.len());
+        assert!(!png.windows(4).any(|window| window == PNG_CHUNK_EXIF));
+    }
+
+    #[test]
+    fn test_strip_location_other() {
+        let mut data = b"GIF89a".to_vec();
+
+        assert!(!strip_location(&mut data));
+        assert_eq!(data, b"GIF89a");
+
+        // Truncated or corrupted metadata is ignored
+        let mut jpeg = make_jpeg(&make_tiff());
+
+        jpeg.truncate(30);
+
+        assert!(!strip_location(&mut jpeg));
+    }
+}
@@ -0,0 +1,241 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::thumbnail::ImageMetadata;
+use crate::download::store;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const INDEX_FILE_NAME: &str = "index.json";
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MediaCacheEntry {
+    pub key: String,
+    pub url: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub last_access: u64,
+    #[serde(default)]
+    pub metadata: Option<ImageMetadata>,
+}
+
+#[derive(Debug, Default)]
+pub struct MediaCacheIndex {
+    directory: Option<PathBuf>,
+    entries: HashMap<String, MediaCacheEntry>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl MediaCacheEntry {
+    pub fn new(key: &str, url: &str, size: u64, content_type: Option<String>) -> Self {
+        Self {
+            key: key.to_string(),
+            url: url.to_string(),
+            size,
+            content_type,
+            last_access: now(),
+            metadata: None,
+        }
+    }
+}
+
+impl MediaCacheIndex {
+    pub fn load(directory: Option<PathBuf>) -> Self {
+        // Read stored entries (if any), forgetting about entries whose file \
+        //   went missing (eg. if the system cleaned up its cache directory)
+        let entries: Vec<MediaCacheEntry> = directory
+            .as_deref()
+            .and_then(|directory| store::read(&directory.join(INDEX_FILE_NAME)))
+            .unwrap_or_default();
+
+        let mut index = Self {
+            directory,
+            entries: HashMap::new(),
+        };
+
+        index.entries = entries
+            .into_iter()
+            .filter(|entry| {
+                index
+                    .path(&entry.key)
+                    .map(|path| path.is_file())
+                    .unwrap_or(false)
+            })
+            .map(|entry| (entry.key.clone(), entry))
+            .collect();
+
+        index
+    }
+
+    pub fn directory(&self) -> Option<&PathBuf> {
+        self.directory.as_ref()
+    }
+
+    pub fn path(&self, key: &str) -> Option<PathBuf> {
+        // Spread files over sub-directories, so that no directory ends up \
+        //   holding too many files
+        let prefix = key.get(..2)?;
+
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(prefix).join(key))
+    }
+
+    pub fn touch(&mut self, key: &str) -> Option<MediaCacheEntry> {
+        // Notice: access times are only stored along with the next index \
+        //   change, therefore the least recently used order is approximate \
+        //   across restarts.
+        let entry = self.entries.get_mut(key)?;
+
+        entry.last_access = now().max(entry.last_access);
+
+        Some(entry.clone())
+    }
+
+    pub fn insert(&mut self, entry: MediaCacheEntry) {
+        self.entries.insert(entry.key.clone(), entry);
+    }
+
+    pub fn evict(&mut self, quota: u64, keep: &str) -> Vec<PathBuf> {
+        // Remove least recently used entries until the cache fits its quota \
+        //   (never removing the entry that was just inserted); the paths of \
+        //   removed entries are returned, so that their files get removed
+        let mut entries = self
+            .entries
+            .values()
+            .filter(|entry| entry.key != keep)
+            .map(|entry| (entry.last_access, entry.key.clone()))
+            .collect::<Vec<_>>();
+
+        entries.sort();
+
+        let mut usage = self.usage();
+        let mut paths = Vec::new();
+
+        for (_, key) in entries {
+            if usage <= quota {
+                break;
+            }
+
+            if let Some(entry) = self.entries.remove(&key) {
+                usage -= entry.size;
+
+                paths.extend(self.path(&key));
+            }
+        }
+
+        paths
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn snapshot(&self) -> Option<(PathBuf, Vec<u8>)> {
+        let path = self.directory.as_ref()?.join(INDEX_FILE_NAME);
+
+        serde_json::to_vec(&self.entries.values().collect::<Vec<_>>())
+            .ok()
+            .map(|data| (path, data))
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_entry(key: &str, size: u64, last_access: u64) -> MediaCacheEntry {
+        let mut entry = MediaCacheEntry::new(key, "https://prose.org/image.png", size, None);
+
+        entry.last_access = last_access;
+        entry
+    }
+
+    #[test]
+    fn test_path() {
+        let index = MediaCacheIndex {
+            directory: Some(PathBuf::from("/cache")),
+            entries: HashMap::new(),
+        };
+
+        assert_eq!(
+            index.path("ab01"),
+            Some(PathBuf::from("/cache").join("ab").join("ab01"))
+        );
+        assert_eq!(index.path("a"), None);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut index = MediaCacheIndex {
+            directory: Some(PathBuf::from("/cache")),
+            entries: HashMap::new(),
+        };
+
+        index.insert(make_entry("aa", 40, 1));
+        index.insert(make_entry("bb", 40, 3));
+        index.insert(make_entry("cc", 40, 2));
+
+        assert_eq!(index.usage(), 120);
+        assert!(index.evict(120, "cc").is_empty());
+
+        // Accessed entries are evicted last
+        assert!(index.touch("aa").is_some());
+
+        index.insert(make_entry("dd", 40, 4));
+
+        assert_eq!(
+            index.evict(120, "dd"),
+            vec![PathBuf::from("/cache").join("cc").join("cc")]
+        );
+        assert_eq!(index.count(), 3);
+
+        // Newly inserted entry is never evicted, even if larger than quota
+        index.insert(make_entry("ee", 500, 0));
+
+        assert_eq!(index.evict(100, "ee").len(), 3);
+        assert_eq!(index.usage(), 500);
+    }
+}
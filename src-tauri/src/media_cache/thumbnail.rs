@@ -0,0 +1,186 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader, ImageResult};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+const THUMBNAIL_CONTENT_TYPE_JPEG: &str = "image/jpeg";
+const THUMBNAIL_CONTENT_TYPE_PNG: &str = "image/png";
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u8,
+}
+
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    pub content_type: &'static str,
+    pub metadata: ImageMetadata,
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+pub fn generate(source: &[u8], size: u32) -> ImageResult<Thumbnail> {
+    // Decode image (only the first frame of animated images), then rotate \
+    //   it as per its EXIF orientation, so that thumbnails need no metadata
+    let mut decoder = ImageReader::new(Cursor::new(source))
+        .with_guessed_format()?
+        .into_decoder()?;
+
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+
+    image.apply_orientation(orientation);
+
+    // Notice: dimensions are those of the image as displayed (ie. once \
+    //   rotated), while the raw orientation is returned for reference.
+    let metadata = ImageMetadata {
+        width: image.width(),
+        height: image.height(),
+        orientation: orientation.to_exif(),
+    };
+
+    // Scale image down to fit requested size (never scaling it up)
+    if image.width() > size || image.height() > size {
+        image = image.thumbnail(size, size);
+    }
+
+    // Encode thumbnail as JPEG, unless it has transparency (which JPEG does \
+    //   not support), in which case PNG is used
+    let mut data = Vec::new();
+
+    let content_type = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(PngEncoder::new(&mut data))?;
+
+        THUMBNAIL_CONTENT_TYPE_PNG
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(
+            JpegEncoder::new_with_quality(&mut data, THUMBNAIL_JPEG_QUALITY),
+        )?;
+
+        THUMBNAIL_CONTENT_TYPE_JPEG
+    };
+
+    Ok(Thumbnail {
+        data,
+        content_type,
+        metadata,
+    })
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn test_generate_jpeg() {
+        let source = encode(
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(400, 200, Rgb([200, 10, 10]))),
+            ImageFormat::Png,
+        );
+
+        let thumbnail = generate(&source, 100).unwrap();
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+
+        assert_eq!(thumbnail.content_type, "image/jpeg");
+        assert_eq!(
+            thumbnail.metadata,
+            ImageMetadata {
+                width: 400,
+                height: 200,
+                orientation: 1
+            }
+        );
+        assert_eq!((image.width(), image.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_generate_png() {
+        let source = encode(
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(30, 60, Rgba([0, 0, 0, 128]))),
+            ImageFormat::Png,
+        );
+
+        // Small images are never scaled up
+        let thumbnail = generate(&source, 100).unwrap();
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+
+        assert_eq!(thumbnail.content_type, "image/png");
+        assert_eq!((image.width(), image.height()), (30, 60));
+    }
+
+    #[test]
+    fn test_generate_oriented() {
+        let source = encode(
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(400, 200, Rgb([10, 200, 10]))),
+            ImageFormat::Jpeg,
+        );
+
+        // Insert an EXIF segment right after the JPEG start marker, holding \
+        //   a 'rotate 90 degrees clockwise' orientation
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+
+        tiff.extend([1, 0]);
+        tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        tiff.extend([0, 0, 0, 0]);
+
+        let mut oriented = source[..2].to_vec();
+
+        oriented.extend([0xff, 0xe1]);
+        oriented.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        oriented.extend(b"Exif\0\0");
+        oriented.extend(tiff);
+        oriented.extend(&source[2..]);
+
+        let thumbnail = generate(&oriented, 100).unwrap();
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+
+        assert_eq!(thumbnail.metadata.orientation, 6);
+        assert_eq!(
+            (thumbnail.metadata.width, thumbnail.metadata.height),
+            (200, 400)
+        );
+        assert_eq!((image.width(), image.height()), (50, 100));
+    }
+
+    #[test]
+    fn test_generate_invalid() {
+        assert!(generate(b"not an image", 100).is_err());
+    }
+}
@@ -16,13 +16,27 @@ use tauri::{AppHandle, Emitter, Manager, Runtime};
  * CONSTANTS
  * ************************************************************************* */
 
-const APP_NAME: &'static str = "Prose";
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const APP_NAME: &str = "Prose";
 
 #[cfg(target_os = "windows")]
-const LABEL_OPEN: &'static str = "Open Prose";
-const LABEL_UPDATES: &'static str = "Check for Updates…";
-const LABEL_SETTINGS: &'static str = "Account Settings…";
-const LABEL_PROFILE: &'static str = "Edit Profile…";
+const LABEL_OPEN: &str = "Open Prose";
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const LABEL_UPDATES: &str = "Check for Updates…";
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const LABEL_SETTINGS: &str = "Account Settings…";
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const LABEL_PROFILE: &str = "Edit Profile…";
+
+#[cfg(target_os = "macos")]
+const LABEL_QUIT: &str = "Quit Prose";
+#[cfg(target_os = "windows")]
+const LABEL_QUIT: &str = "Quit";
+
+#[cfg(target_os = "macos")]
+const ACCELERATOR_QUIT: &str = "Cmd+Q";
+#[cfg(target_os = "windows")]
+const ACCELERATOR_QUIT: &str = "Ctrl+Q";
 
 /**************************************************************************
  * CREATORS
@@ -53,7 +67,7 @@ pub fn create<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Error> {
                 .hide_others()
                 .show_all()
                 .separator()
-                .quit()
+                .item(&quit_item(app)?)
                 .build()?,
         )?;
     }
@@ -66,7 +80,7 @@ pub fn create<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Error> {
 
         #[cfg(not(target_os = "macos"))]
         {
-            submenu = submenu.quit();
+            submenu = submenu.item(&quit_item(app)?);
         }
 
         menu.append(&submenu.build()?)?;
@@ -114,7 +128,7 @@ pub fn tray<R: Runtime>(app: &AppHandle<R>) -> Result<TrayIcon<R>, tauri::Error>
         .text("settings", LABEL_SETTINGS)
         .text("profile", LABEL_PROFILE)
         .separator()
-        .quit()
+        .item(&quit_item(app)?)
         .build()?;
 
     // Build tray icon
@@ -125,11 +139,27 @@ pub fn tray<R: Runtime>(app: &AppHandle<R>) -> Result<TrayIcon<R>, tauri::Error>
         .build(app)
 }
 
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn quit_item<R: Runtime>(app: &AppHandle<R>) -> Result<MenuItem<R>, tauri::Error> {
+    // Notice: the predefined quit item is not used, as on macOS it terminates \
+    //   the application natively, which might not go through exit requests
+    MenuItem::with_id(app, "quit", LABEL_QUIT, true, Some(ACCELERATOR_QUIT))
+}
+
 /**************************************************************************
  * HANDLERS
  * ************************************************************************* */
 
 pub fn handler<R: Runtime>(app: &AppHandle<R>, event: MenuEvent) {
+    // Quit via an exit request, which gets handled on 'RunEvent::ExitRequested' \
+    //   on all platforms, therefore gracefully closing connections first \
+    //   (see 'connection::shutdown')
+    if event.id() == "quit" {
+        app.exit(0);
+
+        return;
+    }
+
     if let Some(window) = app.get_webview_window("main") {
         window.emit("menu:select", event.id()).ok();
     }
@@ -0,0 +1,103 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+use tauri::{App, WebviewUrl, WebviewWindowBuilder, Window};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+pub fn make_window() -> (App<MockRuntime>, Window<MockRuntime>) {
+    let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+
+    let window = WebviewWindowBuilder::new(&app, "main", WebviewUrl::default())
+        .build()
+        .unwrap()
+        .as_ref()
+        .window();
+
+    (app, window)
+}
+
+pub fn make_directory() -> TempDir {
+    // Notice: the directory gets removed once dropped, even if the test \
+    //   panics halfway.
+    tempfile::Builder::new()
+        .prefix("prose-test-")
+        .tempdir()
+        .unwrap()
+}
+
+pub fn list_directory(directory: &Path) -> Vec<String> {
+    let mut filenames = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    filenames.sort();
+    filenames
+}
+
+pub async fn serve_http(status: &'static str, body: &'static [u8]) -> String {
+    serve_http_with(status, "", body).await.0
+}
+
+pub async fn serve_http_with(
+    status: &'static str,
+    headers: &'static str,
+    body: &'static [u8],
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    // Minimal HTTP server, answering every request with the same response \
+    //   (and recording the head of every request)
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let requests_server = requests.clone();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let requests = requests_server.clone();
+
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(size) => request.extend_from_slice(&buffer[..size]),
+                    }
+                }
+
+                requests
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).to_string());
+
+                let head = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    headers,
+                    body.len()
+                );
+
+                stream.write_all(head.as_bytes()).await.ok();
+                stream.write_all(body).await.ok();
+            });
+        }
+    });
+
+    (format!("http://{}/report.pdf", address), requests)
+}
@@ -0,0 +1,836 @@
+// This file is part of prose-app-web
+//
+// Copyright 2024, Prose Foundation
+
+/**************************************************************************
+ * IMPORTS
+ * ************************************************************************* */
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Body, Client, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{Emitter, Manager, Runtime, State, Window};
+use tauri_plugin_dialog::DialogExt;
+use thiserror::Error;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{oneshot, watch};
+use uuid::Uuid;
+
+use crate::download::encryption::{FileEncryption, FileEncryptor, TAG_LENGTH};
+use crate::media_cache::exif;
+
+/**************************************************************************
+ * CONSTANTS
+ * ************************************************************************* */
+
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+// Only those headers can be forwarded to the upload service, as per XEP-0363 \
+//   (HTTP File Upload)
+const UPLOAD_HEADERS_ALLOWED: [&str; 3] = ["authorization", "cookie", "expires"];
+
+const UPLOAD_CONTENT_TYPE_ENCRYPTED: &str = "application/octet-stream";
+
+// Hash announced for picked files, as registered for XEP-0300 (Use of \
+//   Cryptographic Hash Functions in XMPP)
+const UPLOAD_HASH_ALGORITHM: &str = "sha-256";
+
+/**************************************************************************
+ * ENUMERATIONS
+ * ************************************************************************* */
+
+#[derive(Serialize, Deserialize, Debug, Error, PartialEq, Eq)]
+pub enum UploadError {
+    #[error("Could not open file")]
+    CouldNotOpenFile,
+    #[error("Could not upload file")]
+    CouldNotUpload,
+    #[error("Upload already exists")]
+    UploadAlreadyExists,
+    #[error("Upload does not exist")]
+    UploadDoesNotExist,
+    #[error("Upload cancelled")]
+    Cancelled,
+    #[error("Invalid upload header")]
+    InvalidHeader,
+    #[error("Could not generate upload encryption key")]
+    CouldNotGenerateKey,
+    #[error("File was not picked for upload")]
+    FileNotPicked,
+    #[error("Upload URL must be HTTPS")]
+    InsecureUrl,
+}
+
+/**************************************************************************
+ * STRUCTURES
+ * ************************************************************************* */
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventUploadProgress {
+    id: u64,
+    progress: usize,
+    total: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct EventUploadCancelled {
+    id: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UploadPick {
+    token: String,
+    name: String,
+    size: u64,
+    hashes: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadOptions {
+    headers: Option<HashMap<String, String>>,
+    content_type: Option<String>,
+    encrypt: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UploadResult {
+    size: u64,
+    key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UploadFile {
+    path: PathBuf,
+    strip_location: bool,
+}
+
+struct UploadProgress {
+    id: u64,
+    uploaded_bytes: usize,
+    total_bytes: usize,
+    last_size_report: Instant,
+}
+
+struct UploadReader {
+    source: Box<dyn AsyncRead + Unpin + Send>,
+    encryptor: Option<FileEncryptor>,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+pub struct UploadState {
+    client: Client,
+    uploads: Mutex<HashMap<u64, watch::Sender<bool>>>,
+    picked: Mutex<HashMap<String, UploadFile>>,
+}
+
+/**************************************************************************
+ * IMPLEMENTATIONS
+ * ************************************************************************* */
+
+impl UploadProgress {
+    fn report<R: Runtime>(&mut self, window: &Window<R>, force: bool) {
+        // Report upload progress (de-bounced)
+        if force
+            || self.last_size_report.elapsed().as_millis() > 100
+            || self.uploaded_bytes == self.total_bytes
+        {
+            self.last_size_report = Instant::now();
+
+            window
+                .emit(
+                    "upload:progress",
+                    EventUploadProgress {
+                        id: self.id,
+                        progress: self.uploaded_bytes,
+                        total: self.total_bytes,
+                    },
+                )
+                .unwrap();
+        }
+    }
+}
+
+impl UploadReader {
+    fn new(
+        source: impl AsyncRead + Unpin + Send + 'static,
+        encryptor: Option<FileEncryptor>,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            encryptor,
+            buffer: vec![0; UPLOAD_CHUNK_SIZE],
+            finished: false,
+        }
+    }
+
+    async fn next_chunk(&mut self) -> Option<io::Result<(Vec<u8>, usize)>> {
+        // Read next chunk from file, then encrypt it on the fly (if needed); \
+        //   the amount of plaintext bytes read is returned alongside the \
+        //   chunk, so that progress can be reported in terms of file size
+        if self.finished {
+            return None;
+        }
+
+        let size = match self.source.read(&mut self.buffer).await {
+            Ok(size) => size,
+            Err(error) => return Some(Err(error)),
+        };
+
+        if size > 0 {
+            let chunk = match self.encryptor {
+                Some(ref mut encryptor) => encryptor.update(&self.buffer[..size]),
+                None => self.buffer[..size].to_vec(),
+            };
+
+            return Some(Ok((chunk, size)));
+        }
+
+        // End of file reached, append authentication tag (if encrypted)
+        self.finished = true;
+
+        self.encryptor
+            .as_ref()
+            .map(|encryptor| Ok((encryptor.finalize(), 0)))
+    }
+}
+
+impl UploadState {
+    fn new() -> Self {
+        Self {
+            client: Client::new(),
+            uploads: Mutex::new(HashMap::new()),
+            picked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn remember_picked(&self, file: UploadFile) -> String {
+        let token = Uuid::new_v4().to_string();
+
+        self.picked.lock().unwrap().insert(token.clone(), file);
+
+        token
+    }
+
+    fn picked_file(&self, token: &str) -> Option<UploadFile> {
+        self.picked.lock().unwrap().get(token).cloned()
+    }
+}
+
+/**************************************************************************
+ * HELPERS
+ * ************************************************************************* */
+
+async fn ask_files<R: Runtime>(window: &Window<R>) -> Vec<PathBuf> {
+    let (paths_tx, paths_rx) = oneshot::channel();
+
+    window
+        .dialog()
+        .file()
+        .set_parent(window)
+        .pick_files(move |paths| {
+            paths_tx.send(paths).ok();
+        });
+
+    paths_rx
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| path.into_path().ok())
+        .collect()
+}
+
+fn parse_put_url(put_url: &str) -> Result<Url, UploadError> {
+    // Upload slots must be served over HTTPS, as per XEP-0363 (HTTP File \
+    //   Upload), as the file is sent along with authorization headers
+    let url = Url::parse(put_url).map_err(|_| UploadError::InsecureUrl)?;
+
+    if url.scheme() != "https" {
+        return Err(UploadError::InsecureUrl);
+    }
+
+    Ok(url)
+}
+
+fn build_headers(
+    headers: &HashMap<String, String>,
+    content_type: Option<&str>,
+) -> Result<HeaderMap, UploadError> {
+    let mut header_map = HeaderMap::new();
+
+    for (name, value) in headers.iter() {
+        let name = name.to_lowercase();
+
+        // Ignore headers that are not allowed (as per XEP-0363)
+        if !UPLOAD_HEADERS_ALLOWED.contains(&name.as_str()) {
+            continue;
+        }
+
+        // Strip any newline character from header value (as per XEP-0363)
+        let value = value.replace(['\r', '\n'], "");
+
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| UploadError::InvalidHeader)?,
+            HeaderValue::from_str(&value).map_err(|_| UploadError::InvalidHeader)?,
+        );
+    }
+
+    if let Some(content_type) = content_type {
+        header_map.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(content_type).map_err(|_| UploadError::InvalidHeader)?,
+        );
+    }
+
+    Ok(header_map)
+}
+
+fn upload_stream(
+    reader: UploadReader,
+    mut on_read: impl FnMut(usize) + Send + 'static,
+) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + 'static {
+    stream::unfold(reader, |mut reader| async move {
+        reader.next_chunk().await.map(|result| (result, reader))
+    })
+    .map(move |result| {
+        result.map(|(chunk, size)| {
+            on_read(size);
+
+            chunk
+        })
+    })
+}
+
+async fn open_file(upload: &UploadFile) -> Result<(Vec<u8>, File, u64), UploadError> {
+    // Open file to upload, reading its head apart (as it gets stripped from \
+    //   location in memory, if requested), then return the head, the rest of \
+    //   the file and the size of the file as uploaded
+    let mut file = File::open(&upload.path)
+        .await
+        .map_err(|_| UploadError::CouldNotOpenFile)?;
+
+    let file_size = file
+        .metadata()
+        .await
+        .map_err(|_| UploadError::CouldNotOpenFile)?
+        .len();
+
+    let mut head = Vec::new();
+
+    (&mut file)
+        .take(exif::HEAD_LENGTH)
+        .read_to_end(&mut head)
+        .await
+        .map_err(|_| UploadError::CouldNotOpenFile)?;
+
+    let head_size = head.len() as u64;
+
+    if upload.strip_location && exif::strip_location(&mut head) {
+        log::info!("Stripped location from upload image metadata");
+    }
+
+    let size = (file_size + head.len() as u64).saturating_sub(head_size);
+
+    Ok((head, file, size))
+}
+
+async fn prepare_file(upload: &UploadFile) -> Result<(u64, String), UploadError> {
+    // Compute size and hash of the file as uploaded, so that they can be \
+    //   announced when requesting the upload slot
+    let (head, mut file, size) = open_file(upload).await?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+
+    hasher.update(&head);
+
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .map_err(|_| UploadError::CouldNotOpenFile)?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok((size, BASE64.encode(hasher.finalize())))
+}
+
+async fn upload_file<R: Runtime>(
+    window: &Window<R>,
+    client: &Client,
+    id: u64,
+    upload: &UploadFile,
+    put_url: Url,
+    headers: HeaderMap,
+    encryption: Option<&FileEncryption>,
+) -> Result<u64, UploadError> {
+    // Open file to upload (the head of the file is stripped in memory if \
+    //   requested, then the rest of the file follows it as-is)
+    let (head, file, file_size) = open_file(upload).await?;
+
+    // Encrypted files have their authentication tag appended, which must be \
+    //   accounted for in the announced size (as the body is streamed)
+    let body_size = match encryption {
+        Some(_) => file_size + TAG_LENGTH as u64,
+        None => file_size,
+    };
+
+    // Stream file to upload slot, reporting progress as file chunks get read
+    let mut progress = UploadProgress {
+        id,
+        uploaded_bytes: 0,
+        total_bytes: file_size as usize,
+        last_size_report: Instant::now(),
+    };
+
+    progress.report(window, true);
+
+    let progress_window = window.clone();
+
+    let body = Body::wrap_stream(upload_stream(
+        UploadReader::new(
+            io::Cursor::new(head).chain(file),
+            encryption.map(|encryption| encryption.encryptor()),
+        ),
+        move |size| {
+            progress.uploaded_bytes += size;
+            progress.report(&progress_window, false);
+        },
+    ));
+
+    let response = client
+        .put(put_url)
+        .headers(headers)
+        .header(CONTENT_LENGTH, body_size)
+        .body(body)
+        .send()
+        .await
+        .map_err(|_| UploadError::CouldNotUpload)?;
+
+    if !response.status().is_success() {
+        return Err(UploadError::CouldNotUpload);
+    }
+
+    Ok(file_size)
+}
+
+/**************************************************************************
+ * COMMANDS
+ * ************************************************************************* */
+
+#[tauri::command]
+async fn pick<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, UploadState>,
+    strip_location: Option<bool>,
+) -> Result<Vec<UploadPick>, UploadError> {
+    // Ask user to pick files to upload; only files picked there can be \
+    //   uploaded afterwards, as the webview only gets to know opaque tokens \
+    //   referencing them (never the paths themselves)
+    let mut picks = Vec::new();
+
+    for path in ask_files(&window).await {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let upload = UploadFile {
+            path,
+            strip_location: strip_location.unwrap_or(false),
+        };
+
+        // Notice: size and hash account for stripped location (if any), as \
+        //   they must match what gets uploaded
+        let (size, hash) = prepare_file(&upload).await?;
+
+        picks.push(UploadPick {
+            token: state.remember_picked(upload),
+            name,
+            size,
+            hashes: HashMap::from([(UPLOAD_HASH_ALGORITHM.to_string(), hash)]),
+        });
+    }
+
+    Ok(picks)
+}
+
+// Notice: this takes a pick token where a file path was originally \
+//   requested, as letting the webview upload arbitrary paths would allow \
+//   it to read any file from disk. As a consequence, files dropped or \
+//   pasted into the webview cannot be uploaded through this command, and \
+//   must still be uploaded from the webview itself.
+#[tauri::command]
+async fn file<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, UploadState>,
+    id: u64,
+    token: &str,
+    put_url: &str,
+    options: Option<UploadOptions>,
+) -> Result<UploadResult, UploadError> {
+    let options = options.unwrap_or_default();
+
+    // Only upload files that were picked by the user, to secure slots
+    let upload = state.picked_file(token).ok_or(UploadError::FileNotPicked)?;
+
+    let put_url = parse_put_url(put_url)?;
+
+    // Generate a fresh encryption key for this file? (for OMEMO, as per \
+    //   XEP-0454, the key is to be shared in the 'aesgcm://' URL fragment)
+    let encryption = if options.encrypt.unwrap_or(false) {
+        Some(FileEncryption::generate().ok_or(UploadError::CouldNotGenerateKey)?)
+    } else {
+        None
+    };
+
+    // Encrypted files are opaque to the upload service
+    let content_type = match encryption {
+        Some(_) => Some(UPLOAD_CONTENT_TYPE_ENCRYPTED),
+        None => options.content_type.as_deref(),
+    };
+
+    let headers = build_headers(&options.headers.unwrap_or_default(), content_type)?;
+
+    // Register upload (so that it can be cancelled)
+    let (cancel_tx, mut cancel_rx) = watch::channel(false);
+
+    {
+        let mut uploads = state.uploads.lock().unwrap();
+
+        if uploads.contains_key(&id) {
+            return Err(UploadError::UploadAlreadyExists);
+        }
+
+        uploads.insert(id, cancel_tx);
+    }
+
+    // Upload file (unless cancelled, in which case the upload future gets \
+    //   dropped, which aborts the ongoing request)
+    let result = tokio::select! {
+        result = upload_file(
+            &window,
+            &state.client,
+            id,
+            &upload,
+            put_url,
+            headers,
+            encryption.as_ref(),
+        ) => result,
+        _ = cancel_rx.wait_for(|cancelled| *cancelled) => Err(UploadError::Cancelled),
+    };
+
+    state.uploads.lock().unwrap().remove(&id);
+
+    // Forget picked file once uploaded (it can be retried until then)
+    if result.is_ok() {
+        state.picked.lock().unwrap().remove(token);
+    }
+
+    if result == Err(UploadError::Cancelled) {
+        window
+            .emit("upload:cancelled", EventUploadCancelled { id })
+            .unwrap();
+    }
+
+    result.map(|size| UploadResult {
+        size,
+        key: encryption.map(|encryption| encryption.to_fragment()),
+    })
+}
+
+#[tauri::command]
+fn cancel(id: u64, state: State<'_, UploadState>) -> Result<(), UploadError> {
+    state
+        .uploads
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or(UploadError::UploadDoesNotExist)?
+        .send(true)
+        .ok();
+
+    Ok(())
+}
+
+/**************************************************************************
+ * PROVIDERS
+ * ************************************************************************* */
+
+pub fn provide<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("upload")
+        // Notice: commands are kept private to this module, as public ones \
+        //   export command macros clashing with the download commands
+        .invoke_handler(tauri::generate_handler![pick, file, cancel])
+        .setup(|app_handle, _| {
+            app_handle.manage(UploadState::new());
+
+            Ok(())
+        })
+        .build()
+}
+
+/**************************************************************************
+ * TESTS
+ * ************************************************************************* */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{make_directory, make_window};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    async fn receive_http() -> (Url, oneshot::Receiver<(usize, Vec<u8>)>) {
+        // Minimal HTTP server, receiving a single request, then reporting \
+        //   its announced content length and its actual body
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let (received_tx, received_rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+
+            let head_length = loop {
+                if let Some(index) = request.windows(4).position(|bytes| bytes == b"\r\n\r\n") {
+                    break index + 4;
+                }
+
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(size) => request.extend_from_slice(&buffer[..size]),
+                }
+            };
+
+            let content_length = String::from_utf8_lossy(&request[..head_length])
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+
+                    if name.eq_ignore_ascii_case("content-length") {
+                        value.trim().parse::<usize>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(0);
+
+            let mut body = request.split_off(head_length);
+
+            // Read body until the announced length (or until the client \
+            //   stops sending, if it sends less than announced)
+            while body.len() < content_length {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => body.extend_from_slice(&buffer[..size]),
+                }
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .ok();
+
+            received_tx.send((content_length, body)).ok();
+        });
+
+        (url, received_rx)
+    }
+
+    #[test]
+    fn test_build_headers() {
+        let headers = build_headers(
+            &HashMap::from([
+                (
+                    "Authorization".to_string(),
+                    "Basic Zm9vOmJhcg==".to_string(),
+                ),
+                ("Cookie".to_string(), "foo=bar;\r\n user=romeo".to_string()),
+                ("Host".to_string(), "evil.example".to_string()),
+            ]),
+            Some("image/png"),
+        )
+        .unwrap();
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["content-type"], "image/png");
+        assert_eq!(headers["authorization"], "Basic Zm9vOmJhcg==");
+        assert_eq!(headers["cookie"], "foo=bar; user=romeo");
+        assert!(headers.get("host").is_none());
+
+        assert_eq!(
+            build_headers(
+                &HashMap::from([("Expires".to_string(), "\u{0}".to_string())]),
+                None
+            ),
+            Err(UploadError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_parse_put_url() {
+        assert!(parse_put_url("https://upload.prose.org/slot/file.png").is_ok());
+
+        assert_eq!(
+            parse_put_url("http://upload.prose.org/slot/file.png"),
+            Err(UploadError::InsecureUrl)
+        );
+        assert_eq!(
+            parse_put_url("file:///etc/passwd"),
+            Err(UploadError::InsecureUrl)
+        );
+        assert_eq!(
+            parse_put_url("/slot/file.png"),
+            Err(UploadError::InsecureUrl)
+        );
+    }
+
+    #[test]
+    fn test_upload_state_picked() {
+        let state = UploadState::new();
+
+        let file_a = UploadFile {
+            path: PathBuf::from("/tmp/a.png"),
+            strip_location: true,
+        };
+        let file_b = UploadFile {
+            path: PathBuf::from("/tmp/b.png"),
+            strip_location: false,
+        };
+
+        let token_a = state.remember_picked(file_a.clone());
+        let token_b = state.remember_picked(file_b.clone());
+
+        // Tokens are unique, and only ever resolve to picked files
+        assert_ne!(token_a, token_b);
+        assert_eq!(state.picked_file(&token_a), Some(file_a));
+        assert_eq!(state.picked_file(&token_b), Some(file_b));
+        assert_eq!(state.picked_file("/tmp/a.png"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_upload_file_announced_size() {
+        let (_app, window) = make_window();
+        let client = Client::new();
+        let directory = make_directory();
+        let path = directory.path().join("image.png");
+
+        // PNG image holding an EXIF chunk (which gets removed if stripping \
+        //   location)
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        data.extend([0, 0, 0, 4]);
+        data.extend(b"eXIf");
+        data.extend([1, 2, 3, 4, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 2]);
+        data.extend(b"IDAT");
+        data.extend([5, 6, 0, 0, 0, 0]);
+
+        std::fs::write(&path, &data).unwrap();
+
+        for (strip_location, encrypted) in [(false, false), (true, false), (true, true)] {
+            let upload = UploadFile {
+                path: path.clone(),
+                strip_location,
+            };
+
+            let encryption = encrypted.then(|| FileEncryption::generate().unwrap());
+            let (size, hash) = prepare_file(&upload).await.unwrap();
+            let (url, received) = receive_http().await;
+
+            let uploaded_size = upload_file(
+                &window,
+                &client,
+                1,
+                &upload,
+                url,
+                HeaderMap::new(),
+                encryption.as_ref(),
+            )
+            .await
+            .unwrap();
+
+            let (content_length, body) = received.await.unwrap();
+
+            // Announced size must match the bytes actually sent
+            assert_eq!(content_length, body.len());
+            assert_eq!(uploaded_size, size);
+
+            if encrypted {
+                assert_eq!(body.len() as u64, size + TAG_LENGTH as u64);
+            } else {
+                assert_eq!(body.len() as u64, size);
+                assert_eq!(hash, BASE64.encode(Sha256::digest(&body)));
+            }
+
+            assert_eq!(size == data.len() as u64, !strip_location);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_stream_encrypted() {
+        let directory = make_directory();
+        let path = directory.path().join("upload.bin");
+        let data = (0..(UPLOAD_CHUNK_SIZE * 2 + 100))
+            .map(|index| (index % 251) as u8)
+            .collect::<Vec<_>>();
+
+        std::fs::write(&path, &data).unwrap();
+
+        let encryption = FileEncryption::generate().unwrap();
+        let read_bytes = Arc::new(AtomicUsize::new(0));
+        let read_bytes_stream = read_bytes.clone();
+
+        let chunks = upload_stream(
+            UploadReader::new(
+                File::open(&path).await.unwrap(),
+                Some(encryption.encryptor()),
+            ),
+            move |size| {
+                read_bytes_stream.fetch_add(size, Ordering::SeqCst);
+            },
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        let ciphertext = chunks
+            .into_iter()
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .concat();
+
+        // Ciphertext must decrypt back to the original file
+        let mut decryptor = encryption.decryptor();
+        let plaintext = decryptor.update(&ciphertext);
+
+        assert_eq!(read_bytes.load(Ordering::SeqCst), data.len());
+        assert_eq!(ciphertext.len(), data.len() + TAG_LENGTH);
+        assert_eq!(plaintext, data);
+        assert!(decryptor.finalize());
+    }
+}
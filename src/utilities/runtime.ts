@@ -112,12 +112,65 @@ enum RuntimeConnectionMethod {
   Relayed = "relayed"
 }
 
+enum RuntimeDownloadStatus {
+  // Queued status.
+  Queued = "queued",
+  // Downloading status.
+  Downloading = "downloading",
+  // Paused status.
+  Paused = "paused",
+  // Completed status.
+  Completed = "completed",
+  // Failed status.
+  Failed = "failed",
+  // Cancelled status.
+  Cancelled = "cancelled",
+  // Deferred status (on metered connections).
+  Deferred = "deferred"
+}
+
+enum RuntimeDownloadFileKind {
+  // ELF binary file kind.
+  Elf = "elf",
+  // AppImage file kind.
+  AppImage = "app-image",
+  // Mach-O binary file kind.
+  MachO = "mach-o",
+  // Windows executable file kind.
+  WindowsExecutable = "windows-executable",
+  // Script file kind.
+  Script = "script",
+  // Desktop entry file kind.
+  DesktopEntry = "desktop-entry"
+}
+
 /**************************************************************************
  * TYPES
  * ************************************************************************* */
 
 type RuntimeNotificationClickHandler = () => void;
-type RuntimeProgressHandler = (progress: number, total: number) => void;
+type RuntimeProgressHandler = (
+  progress: number,
+  total: number,
+  details?: RuntimeProgressDetails
+) => void;
+type RuntimeDownloadWarningHandler = (
+  id: number,
+  path: string,
+  kind: RuntimeDownloadFileKind
+) => void;
+type RuntimeDownloadStartedHandler = (id: number) => void;
+type RuntimeDownloadCompletedHandler = (
+  id: number,
+  path: string,
+  size: number
+) => void;
+type RuntimeDownloadFailedHandler = (
+  id: number,
+  reason: RuntimeDownloadFailureReason
+) => void;
+type RuntimeDownloadRestoredHandler = (id: number, resumed: boolean) => void;
+type RuntimeDownloadFailureReason = string | { [reason: string]: unknown };
 type RuntimeFocusHandler = (focused: boolean) => void;
 type RuntimeOpenHandler = (protocol: string, path: string) => void;
 type RuntimeMenuHandler = (menu: string) => Promise<void>;
@@ -136,10 +189,105 @@ type RuntimeConnectionID = string;
  * INTERFACES
  * ************************************************************************* */
 
+interface RuntimeProgressDetails {
+  indeterminate: boolean;
+  speed: number;
+  eta: number | null;
+}
+
 interface RuntimeDownloadProgressPayload {
   id: number;
   progress: number;
   total: number;
+  indeterminate: boolean;
+  speed: number;
+  eta: number | null;
+}
+
+interface RuntimeDownloadStartedPayload {
+  id: number;
+}
+
+interface RuntimeDownloadCompletedPayload {
+  id: number;
+  path: string;
+  size: number;
+}
+
+interface RuntimeDownloadFailedPayload {
+  id: number;
+  reason: RuntimeDownloadFailureReason;
+}
+
+interface RuntimeDownloadRestoredPayload {
+  id: number;
+  resumed: boolean;
+}
+
+interface RuntimeDownloadOptions {
+  askLocation?: boolean;
+  hashes?: { [algorithm: string]: string };
+  size?: number;
+  sender?: string;
+  automatic?: boolean;
+  account?: string;
+  headers?: { [name: string]: string };
+}
+
+interface RuntimeDownloadRecord {
+  id: number;
+  url: string;
+  filename: string;
+  path: string | null;
+  size: number | null;
+  checksum: string | null;
+  completed_at: number | null;
+  status: RuntimeDownloadStatus;
+  warning: RuntimeDownloadFileKind | null;
+  sender: string | null;
+}
+
+interface RuntimeDownloadWarningPayload {
+  id: number;
+  path: string;
+  kind: RuntimeDownloadFileKind;
+}
+
+interface RuntimeUploadProgressPayload {
+  id: number;
+  progress: number;
+  total: number;
+}
+
+interface RuntimeUploadOptions {
+  headers?: { [name: string]: string };
+  contentType?: string;
+  encrypt?: boolean;
+}
+
+interface RuntimeUploadPick {
+  token: string;
+  name: string;
+  size: number;
+  hashes: { [algorithm: string]: string };
+}
+
+interface RuntimeUploadResult {
+  size: number;
+  key: string | null;
+}
+
+interface RuntimeMediaCacheUsage {
+  size: number;
+  quota: number;
+  count: number;
+}
+
+interface RuntimeMediaCacheThumbnail {
+  url: string;
+  width: number;
+  height: number;
+  orientation: number;
 }
 
 interface RuntimeNotificationInteractionPayload {
@@ -232,6 +380,12 @@ class UtilitiesRuntime {
       open: null as RuntimeOpenHandler | null,
       menu: null as RuntimeMenuHandler | null,
       download: new Map() as Map<number, RuntimeProgressHandler>,
+      downloadWarning: null as RuntimeDownloadWarningHandler | null,
+      downloadStarted: null as RuntimeDownloadStartedHandler | null,
+      downloadCompleted: null as RuntimeDownloadCompletedHandler | null,
+      downloadFailed: null as RuntimeDownloadFailedHandler | null,
+      downloadRestored: null as RuntimeDownloadRestoredHandler | null,
+      upload: new Map() as Map<number, RuntimeProgressHandler>,
       notification: new Map() as Map<string, RuntimeNotificationHandlers>
     },
 
@@ -279,6 +433,31 @@ class UtilitiesRuntime {
     this.__handlers.global.menu = null;
   }
 
+  registerDownloadWarningHandler(
+    handler: RuntimeDownloadWarningHandler | null
+  ): void {
+    // Register handler for downloads deemed dangerous (eg. executables)
+    this.__handlers.global.downloadWarning = handler;
+  }
+
+  registerDownloadLifecycleHandlers({
+    started,
+    completed,
+    failed,
+    restored
+  }: {
+    started: RuntimeDownloadStartedHandler | null;
+    completed: RuntimeDownloadCompletedHandler | null;
+    failed: RuntimeDownloadFailedHandler | null;
+    restored: RuntimeDownloadRestoredHandler | null;
+  }): void {
+    // Register handlers for download lifecycle events (for all downloads)
+    this.__handlers.global.downloadStarted = started;
+    this.__handlers.global.downloadCompleted = completed;
+    this.__handlers.global.downloadFailed = failed;
+    this.__handlers.global.downloadRestored = restored;
+  }
+
   registerConnectionHandlers(
     id: RuntimeConnectionID,
     {
@@ -320,7 +499,8 @@ class UtilitiesRuntime {
   async requestFileDownload(
     url: string,
     filename: string | null = null,
-    progressHandler?: RuntimeProgressHandler
+    progressHandler?: RuntimeProgressHandler,
+    options: RuntimeDownloadOptions = {}
   ): Promise<void> {
     if (this.__isApplication === true) {
       // Request to download file via Tauri API (application build)
@@ -333,7 +513,8 @@ class UtilitiesRuntime {
       await tauriInvoke("plugin:download|file", {
         id,
         url,
-        filename
+        filename,
+        options
       });
 
       this.__handlers.global.download.delete(id);
@@ -356,6 +537,395 @@ class UtilitiesRuntime {
     }
   }
 
+  async requestFileUploadPick(
+    stripLocation = false
+  ): Promise<RuntimeUploadPick[]> {
+    if (this.__isApplication === true) {
+      // Request to pick files to upload via Tauri API (application build); \
+      //   only opaque tokens are returned, which can then be uploaded
+      return await tauriInvoke("plugin:upload|pick", { stripLocation });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file upload pick on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileUpload(
+    id: number,
+    token: string,
+    putUrl: string,
+    progressHandler?: RuntimeProgressHandler,
+    options?: RuntimeUploadOptions
+  ): Promise<RuntimeUploadResult> {
+    if (this.__isApplication === true) {
+      // Request to upload file via Tauri API (application build)
+      if (progressHandler !== undefined) {
+        this.__handlers.global.upload.set(id, progressHandler);
+      }
+
+      try {
+        return await tauriInvoke("plugin:upload|file", {
+          id,
+          token,
+          putUrl,
+          options: options ?? null
+        });
+      } finally {
+        this.__handlers.global.upload.delete(id);
+      }
+    } else {
+      throw new Error(
+        "Attempted to request file upload on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileUploadCancel(id: number): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to cancel upload via Tauri API (application build)
+      await tauriInvoke("plugin:upload|cancel", { id });
+    } else {
+      throw new Error(
+        "Attempted to request file upload cancel on unsupported platform"
+      );
+    }
+  }
+
+  async requestMediaCacheFetch(url: string, hash?: string): Promise<string> {
+    if (this.__isApplication === true) {
+      // Request to fetch media into cache via Tauri API (application build)
+      return await tauriInvoke("plugin:media-cache|fetch", {
+        url,
+        hash: hash ?? null
+      });
+    } else {
+      // Media is cached by the browser itself (Web build)
+      return url;
+    }
+  }
+
+  async requestMediaCacheThumbnail(
+    url: string,
+    size: number,
+    hash?: string
+  ): Promise<RuntimeMediaCacheThumbnail> {
+    if (this.__isApplication === true) {
+      // Request to generate media thumbnail via Tauri API (application build)
+      return await tauriInvoke("plugin:media-cache|thumbnail", {
+        url,
+        hash: hash ?? null,
+        size
+      });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request media cache thumbnail on unsupported platform"
+      );
+    }
+  }
+
+  async requestMediaCacheUsage(): Promise<RuntimeMediaCacheUsage> {
+    if (this.__isApplication === true) {
+      // Request to get media cache usage via Tauri API (application build)
+      return await tauriInvoke("plugin:media-cache|usage");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request media cache usage on unsupported platform"
+      );
+    }
+  }
+
+  async requestMediaCacheClear(): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to clear media cache via Tauri API (application build)
+      await tauriInvoke("plugin:media-cache|clear");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request media cache clear on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadList(): Promise<RuntimeDownloadRecord[]> {
+    if (this.__isApplication === true) {
+      // Request to list downloads via Tauri API (application build)
+      return await tauriInvoke("plugin:download|list");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download list on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadRestore(): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to restore interrupted downloads via Tauri API (application \
+      //   build); results are reported to the restored lifecycle handler
+      await tauriInvoke("plugin:download|restore");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download restore on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadRetry(
+    id: number,
+    progressHandler?: RuntimeProgressHandler
+  ): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to retry download via Tauri API (application build)
+      if (progressHandler !== undefined) {
+        this.__handlers.global.download.set(id, progressHandler);
+      }
+
+      try {
+        await tauriInvoke("plugin:download|retry", { id });
+      } finally {
+        this.__handlers.global.download.delete(id);
+      }
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download retry on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadHistoryClear(): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to clear download history via Tauri API (application build)
+      await tauriInvoke("plugin:download|clear_history");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download history clear on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadDirectoryGet(): Promise<string> {
+    if (this.__isApplication === true) {
+      // Request to get download directory via Tauri API (application build)
+      return await tauriInvoke("plugin:download|get_directory");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download directory get on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadDirectorySet(
+    directory: string | null
+  ): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to set download directory via Tauri API (application build)
+      await tauriInvoke("plugin:download|set_directory", { directory });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download directory set on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadDirectoryChoose(): Promise<string | null> {
+    if (this.__isApplication === true) {
+      // Request to choose download directory via Tauri API (application build)
+      return await tauriInvoke("plugin:download|choose_directory");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download directory choose on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadMaximumSizeGet(): Promise<number | null> {
+    if (this.__isApplication === true) {
+      // Request to get maximum download size via Tauri API (application build)
+      return await tauriInvoke("plugin:download|get_maximum_size");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download maximum size get on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadMaximumSizeSet(size: number | null): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to set maximum download size via Tauri API (application build)
+      await tauriInvoke("plugin:download|set_maximum_size", { size });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download maximum size set on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadRateLimitGet(): Promise<number | null> {
+    if (this.__isApplication === true) {
+      // Request to get global download rate limit via Tauri API (application \
+      //   build)
+      return await tauriInvoke("plugin:download|get_rate_limit");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download rate limit get on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadRateLimitSet(
+    rate: number | null,
+    id?: number
+  ): Promise<void> {
+    if (this.__isApplication === true) {
+      if (id !== undefined) {
+        // Request to set ongoing download rate limit via Tauri API \
+        //   (application build)
+        await tauriInvoke("plugin:download|set_download_rate_limit", {
+          id,
+          rate
+        });
+      } else {
+        // Request to set global download rate limit via Tauri API \
+        //   (application build)
+        await tauriInvoke("plugin:download|set_rate_limit", { rate });
+      }
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download rate limit set on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadMeteredThresholdGet(): Promise<number | null> {
+    if (this.__isApplication === true) {
+      // Request to get metered connection download threshold via Tauri API \
+      //   (application build)
+      return await tauriInvoke("plugin:download|get_metered_threshold");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download metered threshold get on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadMeteredThresholdSet(
+    size: number | null
+  ): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to set metered connection download threshold via Tauri API \
+      //   (application build)
+      await tauriInvoke("plugin:download|set_metered_threshold", { size });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download metered threshold set on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadUserAgentGet(): Promise<string> {
+    if (this.__isApplication === true) {
+      // Request to get download user agent via Tauri API (application build)
+      return await tauriInvoke("plugin:download|get_user_agent");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download user agent get on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadUserAgentSet(
+    userAgent: string | null
+  ): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to set download user agent via Tauri API (application build)
+      await tauriInvoke("plugin:download|set_user_agent", { userAgent });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download user agent set on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadCookiesClear(account: string): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to clear account download cookies via Tauri API (application \
+      //   build)
+      await tauriInvoke("plugin:download|clear_cookies", { account });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download cookies clear on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadAllowExecution(id: number): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to allow download execution via Tauri API (application build)
+      await tauriInvoke("plugin:download|allow_execution", { id });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download execution allow on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadReveal(id: number): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to reveal download via Tauri API (application build)
+      await tauriInvoke("plugin:download|reveal", { id });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download reveal on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadOpen(path: string): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to open downloaded file via Tauri API (application build)
+      await tauriInvoke("plugin:download|open_file", { path });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download open on unsupported platform"
+      );
+    }
+  }
+
+  async requestFileDownloadRevealInFolder(path: string): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to reveal downloaded file via Tauri API (application build)
+      await tauriInvoke("plugin:download|reveal_in_folder", { path });
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request file download reveal in folder on unsupported " +
+          "platform"
+      );
+    }
+  }
+
   async requestNotificationSend(
     title: string,
     body: string,
@@ -602,7 +1172,16 @@ class UtilitiesRuntime {
 
       if (update !== null) {
         try {
-          await update.downloadAndInstall();
+          await update.download();
+
+          // Disconnect all connections before installing
+          // Notice: installers may exit the application right away (eg. on \
+          //   Windows), and restarting afterwards cannot be deferred, which \
+          //   would not let us gracefully close XMPP sessions, therefore do \
+          //   it beforehand.
+          await this.requestConnectionDisconnectAll();
+
+          await update.install();
 
           // Interactive mode? Ask user if they want to restart now
           if (isInteractive === true) {
@@ -747,6 +1326,18 @@ class UtilitiesRuntime {
     }
   }
 
+  async requestConnectionDisconnectAll(): Promise<void> {
+    if (this.__isApplication === true) {
+      // Request to disconnect all via Tauri API (application build)
+      await tauriInvoke("plugin:connection|disconnect_all");
+    } else {
+      // This method should NEVER be used on other platforms
+      throw new Error(
+        "Attempted to request connection disconnect all on unsupported platform"
+      );
+    }
+  }
+
   async requestConnectionDestroy(id: RuntimeConnectionID): Promise<void> {
     if (this.__isApplication === true) {
       // Request to destroy via Tauri API (application build)
@@ -813,11 +1404,98 @@ class UtilitiesRuntime {
       // Register listeners via Tauri API (application build)
       this.__states.focused = true;
 
-      tauriWindow().listen<RuntimeDownloadProgressPayload>(
-        "download:progress",
+      // Restore interrupted downloads once all download listeners are \
+      //   registered, otherwise restoration results would get lost
+      Promise.all([
+        tauriWindow().listen<RuntimeDownloadProgressPayload>(
+          "download:progress",
+
+          ({ payload }) => {
+            const progressHandler = this.__handlers.global.download.get(
+              payload.id
+            );
+
+            if (progressHandler !== undefined) {
+              progressHandler(payload.progress, payload.total, {
+                indeterminate: payload.indeterminate,
+                speed: payload.speed,
+                eta: payload.eta
+              });
+            }
+          }
+        ),
+
+        tauriWindow().listen<RuntimeDownloadStartedPayload>(
+          "download:started",
+
+          ({ payload }) => {
+            if (this.__handlers.global.downloadStarted !== null) {
+              this.__handlers.global.downloadStarted(payload.id);
+            }
+          }
+        ),
+
+        tauriWindow().listen<RuntimeDownloadCompletedPayload>(
+          "download:completed",
+
+          ({ payload }) => {
+            if (this.__handlers.global.downloadCompleted !== null) {
+              this.__handlers.global.downloadCompleted(
+                payload.id,
+                payload.path,
+                payload.size
+              );
+            }
+          }
+        ),
+
+        tauriWindow().listen<RuntimeDownloadFailedPayload>(
+          "download:failed",
+
+          ({ payload }) => {
+            if (this.__handlers.global.downloadFailed !== null) {
+              this.__handlers.global.downloadFailed(payload.id, payload.reason);
+            }
+          }
+        ),
+
+        tauriWindow().listen<RuntimeDownloadRestoredPayload>(
+          "download:restored",
+
+          ({ payload }) => {
+            if (this.__handlers.global.downloadRestored !== null) {
+              this.__handlers.global.downloadRestored(
+                payload.id,
+                payload.resumed
+              );
+            }
+          }
+        ),
+
+        tauriWindow().listen<RuntimeDownloadWarningPayload>(
+          "download:warning",
+
+          ({ payload }) => {
+            if (this.__handlers.global.downloadWarning !== null) {
+              this.__handlers.global.downloadWarning(
+                payload.id,
+                payload.path,
+                payload.kind
+              );
+            }
+          }
+        )
+      ])
+        .then(() => this.requestFileDownloadRestore())
+        .catch(error => {
+          logger.error("Could not restore interrupted downloads", error);
+        });
+
+      tauriWindow().listen<RuntimeUploadProgressPayload>(
+        "upload:progress",
 
         ({ payload }) => {
-          const progressHandler = this.__handlers.global.download.get(
+          const progressHandler = this.__handlers.global.upload.get(
             payload.id
           );
 
@@ -940,9 +1618,22 @@ export {
   RuntimeUrlOpenTarget,
   RuntimeConnectionState,
   RuntimeConnectionMethod,
+  RuntimeDownloadStatus,
+  RuntimeDownloadFileKind,
   platform,
   context,
   translucent
 };
-export type { RuntimeConnectionID };
+export type {
+  RuntimeConnectionID,
+  RuntimeDownloadOptions,
+  RuntimeDownloadRecord,
+  RuntimeDownloadFailureReason,
+  RuntimeProgressDetails,
+  RuntimeUploadOptions,
+  RuntimeUploadPick,
+  RuntimeUploadResult,
+  RuntimeMediaCacheUsage,
+  RuntimeMediaCacheThumbnail
+};
 export default new UtilitiesRuntime();